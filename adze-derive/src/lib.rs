@@ -0,0 +1,129 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
+
+/// Implements `adze::renderer::buffer::Vertex` for a `#[repr(C)]` struct, building its
+/// `BufferLayout` from the fields in declaration order.
+///
+/// Every field must implement `VertexAttribute`. Fields can be marked `#[vertex(normalized)]`
+/// and renamed with `#[vertex(name = "...")]`. Per instance data is declared on the struct
+/// with `#[vertex(divisor = 1)]`.
+#[proc_macro_derive(Vertex, attributes(vertex))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_vertex(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into()
+    }
+}
+
+fn expand_vertex(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&input.generics, "Vertex can't be derived for generic structs"));
+    }
+
+    if !has_repr_c(input) {
+        return Err(syn::Error::new(Span::call_site(), "Vertex requires #[repr(C)] so fields keep their order"));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(name, "Vertex can only be derived for structs with named fields"))
+        },
+        _ => return Err(syn::Error::new_spanned(name, "Vertex can only be derived for structs"))
+    };
+
+    let mut divisor = 0_u32;
+    for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("vertex")) {
+        for nested in parse_vertex_attr(attr)? {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("divisor") => match value.lit {
+                    Lit::Int(lit) => divisor = lit.base10_parse()?,
+                    lit => return Err(syn::Error::new_spanned(lit, "expected an integer"))
+                },
+                nested => return Err(syn::Error::new_spanned(nested, "unknown vertex attribute"))
+            }
+        }
+    }
+
+    let mut elements = Vec::new();
+    let mut field_types = Vec::new();
+    for field in fields {
+        let ty = &field.ty;
+        let mut element_name = field.ident.as_ref().unwrap().to_string();
+        let mut normalized = false;
+
+        for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("vertex")) {
+            for nested in parse_vertex_attr(attr)? {
+                match nested {
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("normalized") => normalized = true,
+                    NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("name") => match value.lit {
+                        Lit::Str(lit) => element_name = lit.value(),
+                        lit => return Err(syn::Error::new_spanned(lit, "expected a string"))
+                    },
+                    nested => return Err(syn::Error::new_spanned(nested, "unknown vertex attribute"))
+                }
+            }
+        }
+
+        elements.push(quote! {
+            ::adze::renderer::buffer::BufferElement::new(
+                ::std::string::String::from(#element_name),
+                <#ty as ::adze::renderer::buffer::VertexAttribute>::DATA_TYPE,
+                #normalized
+            )
+        });
+        field_types.push(ty);
+    }
+
+    Ok(quote! {
+        const _: () = {
+            fn assert_pod<T: ::adze::bytemuck::Pod>() {}
+            #[allow(dead_code)]
+            fn assert_fields_are_pod() {
+                #(assert_pod::<#field_types>();)*
+            }
+
+            // the layout is packed, any padding would shift the attributes that follow it
+            assert!(
+                ::core::mem::size_of::<#name>() == 0 #(+ ::core::mem::size_of::<#field_types>())*,
+                "Vertex structs can't contain padding"
+            );
+        };
+
+        unsafe impl ::adze::bytemuck::Zeroable for #name {}
+        unsafe impl ::adze::bytemuck::Pod for #name {}
+
+        impl ::adze::renderer::buffer::Vertex for #name {
+            fn layout() -> ::adze::renderer::buffer::BufferLayout {
+                ::adze::renderer::buffer::BufferLayout::new(vec![#(#elements),*]).with_divisor(#divisor)
+            }
+        }
+    })
+}
+
+fn parse_vertex_attr(attr: &Attribute) -> syn::Result<Punctuated<NestedMeta, Comma>> {
+    match attr.parse_meta()? {
+        Meta::List(list) => Ok(list.nested),
+        meta => Err(syn::Error::new_spanned(meta, "expected #[vertex(...)]"))
+    }
+}
+
+fn has_repr_c(input: &DeriveInput) -> bool {
+    input.attrs.iter()
+        .filter(|attr| attr.path.is_ident("repr"))
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| match meta {
+            Meta::List(list) => list.nested.iter().any(|nested| match nested {
+                NestedMeta::Meta(meta) => meta.path().is_ident("C"),
+                _ => false
+            }),
+            _ => false
+        })
+}
@@ -0,0 +1,350 @@
+//! The asset pack format, read by `adze::vfs` and written by `adze-pack`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use miniz_oxide::inflate::stream::InflateState;
+use miniz_oxide::{DataFormat, MZFlush, MZStatus};
+
+const MAGIC: &[u8; 8] = b"ADZEPACK";
+const VERSION: u32 = 1;
+
+// magic, version and the offset of the index
+const HEADER_SIZE: u64 = 8 + 4 + 8;
+// an entry in the index with an empty name: name length, offset, sizes, compression and hash
+const MIN_ENTRY_SIZE: u64 = 4 + 8 + 8 + 8 + 1 + 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Deflate
+}
+
+impl Compression {
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1
+        }
+    }
+
+    fn from_byte(byte: u8) -> io::Result<Compression> {
+        match byte {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(invalid_data(format!("unknown compression {}", byte)))
+        }
+    }
+}
+
+/// Where a file is stored in a pack. `hash` is the CRC32 of the uncompressed contents,
+/// checked every time the file is read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackEntry {
+    pub offset: u64,
+    pub stored_size: u64,
+    pub size: u64,
+    pub compression: Compression,
+    pub hash: u32
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Inflates deflated data, failing as soon as it grows past `size` bytes so a corrupt pack
+/// can't make it allocate more than the file says it holds.
+fn inflate(stored: &[u8], size: u64) -> Result<Vec<u8>, String> {
+    let mut state = InflateState::new_boxed(DataFormat::Raw);
+    let mut data = vec![];
+    let mut chunk = vec![0; 64 * 1024];
+    let mut input = stored;
+    loop {
+        let result = miniz_oxide::inflate::stream::inflate(&mut state, input, &mut chunk, MZFlush::None);
+        input = &input[result.bytes_consumed..];
+        data.extend_from_slice(&chunk[..result.bytes_written]);
+        if data.len() as u64 > size {
+            return Err(format!("it inflates to more than its {} bytes", size));
+        }
+        match result.status {
+            Ok(MZStatus::StreamEnd) => return Ok(data),
+            Ok(_) if result.bytes_consumed == 0 && result.bytes_written == 0 => return Err("it is cut short".to_string()),
+            Ok(_) => (),
+            Err(err) => return Err(format!("{:?}", err))
+        }
+    }
+}
+
+/// Normalizes a path inside a pack to forward slashes without a leading `./` or `/`. `None`
+/// for paths with a `..`, which could lead out of the directory they are under.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let parts: Vec<&str> = path.split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    if parts.contains(&"..") {
+        return None;
+    }
+    Some(parts.join("/"))
+}
+
+/// Builds a pack file. The layout is a header, the file contents one after another and an
+/// index at the end mapping each path to its `PackEntry`, all integers little endian.
+pub struct PackWriter {
+    writer: BufWriter<File>,
+    entries: Vec<(String, PackEntry)>,
+    offset: u64
+}
+
+impl PackWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<PackWriter> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        // patched with the index offset in finish
+        writer.write_all(&0_u64.to_le_bytes())?;
+
+        Ok(PackWriter {
+            writer,
+            entries: vec![],
+            offset: HEADER_SIZE
+        })
+    }
+
+    /// Adds a file. Compressed data that ends up larger than the original is stored as is.
+    pub fn add(&mut self, path: &str, data: &[u8], compression: Compression) -> io::Result<()> {
+        let path = normalize_path(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} can't be packed, it has a ..", path)))?;
+        let compressed = match compression {
+            Compression::Deflate => Some(miniz_oxide::deflate::compress_to_vec(data, 6)).filter(|compressed| compressed.len() < data.len()),
+            Compression::None => None
+        };
+        let (stored, compression) = match &compressed {
+            Some(compressed) => (compressed.as_slice(), Compression::Deflate),
+            None => (data, Compression::None)
+        };
+
+        self.writer.write_all(stored)?;
+        self.entries.push((path, PackEntry {
+            offset: self.offset,
+            stored_size: stored.len() as u64,
+            size: data.len() as u64,
+            compression,
+            hash: crc32fast::hash(data)
+        }));
+        self.offset += stored.len() as u64;
+        Ok(())
+    }
+
+    pub fn get_entries(&self) -> &[(String, PackEntry)] {
+        &self.entries
+    }
+
+    /// Writes the index and closes the pack.
+    pub fn finish(mut self) -> io::Result<()> {
+        let index_offset = self.offset;
+        self.writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for (path, entry) in self.entries.iter() {
+            self.writer.write_all(&(path.len() as u32).to_le_bytes())?;
+            self.writer.write_all(path.as_bytes())?;
+            self.writer.write_all(&entry.offset.to_le_bytes())?;
+            self.writer.write_all(&entry.stored_size.to_le_bytes())?;
+            self.writer.write_all(&entry.size.to_le_bytes())?;
+            self.writer.write_all(&[entry.compression.to_byte()])?;
+            self.writer.write_all(&entry.hash.to_le_bytes())?;
+        }
+
+        self.writer.seek(SeekFrom::Start(8 + 4))?;
+        self.writer.write_all(&index_offset.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+/// A pack opened for reading. Only the index is kept in memory, files are read from disk
+/// when asked for, so a pack can be shared between loader threads.
+#[derive(Clone)]
+pub struct AssetPack {
+    path: PathBuf,
+    entries: HashMap<String, PackEntry>
+}
+
+impl AssetPack {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<AssetPack> {
+        let file = File::open(&path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data(format!("{} is not an asset pack", path.as_ref().display())));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!("unsupported asset pack version {}", version)));
+        }
+
+        // sizes read from the file are checked against it before anything is allocated for them
+        let index_offset = read_u64(&mut reader)?;
+        if index_offset < HEADER_SIZE || index_offset > file_size {
+            return Err(invalid_data(format!("the index of {} is outside of it", path.as_ref().display())));
+        }
+        let index_size = file_size - index_offset;
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let count = read_u32(&mut reader)?;
+        if count as u64 * MIN_ENTRY_SIZE > index_size {
+            return Err(invalid_data(format!("{} lists more files than its index holds", path.as_ref().display())));
+        }
+        let mut entries = HashMap::with_capacity(count as usize);
+        for _ in 0..count {
+            let name_size = read_u32(&mut reader)? as u64;
+            if name_size > index_size {
+                return Err(invalid_data(format!("{} has a name longer than its index", path.as_ref().display())));
+            }
+            let mut name = vec![0; name_size as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|err| invalid_data(err.to_string()))?;
+            let entry = PackEntry {
+                offset: read_u64(&mut reader)?,
+                stored_size: read_u64(&mut reader)?,
+                size: read_u64(&mut reader)?,
+                compression: Compression::from_byte(read_u8(&mut reader)?)?,
+                hash: read_u32(&mut reader)?
+            };
+            if entry.offset < HEADER_SIZE || entry.offset.checked_add(entry.stored_size).is_none_or(|end| end > index_offset) {
+                return Err(invalid_data(format!("{} is outside of the files in {}", name, path.as_ref().display())));
+            }
+            entries.insert(name, entry);
+        }
+
+        Ok(AssetPack {
+            path: path.as_ref().to_path_buf(),
+            entries
+        })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_entry(&self, path: &str) -> Option<&PackEntry> {
+        self.entries.get(&normalize_path(path)?)
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.get_entry(path).is_some()
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        self.entries.keys()
+    }
+
+    /// Reads, decompresses and verifies a file.
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let entry = self.get_entry(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not in {}", path, self.path.display())))?;
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut stored = vec![0; entry.stored_size as usize];
+        file.read_exact(&mut stored)?;
+
+        let data = match entry.compression {
+            Compression::None => stored,
+            Compression::Deflate => inflate(&stored, entry.size).map_err(|err| invalid_data(format!("could not decompress {}: {}", path, err)))?
+        };
+
+        if data.len() as u64 != entry.size || crc32fast::hash(&data) != entry.hash {
+            return Err(invalid_data(format!("{} is corrupted in {}", path, self.path.display())));
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use std::fs;
+    use std::io;
+
+    use crate::{AssetPack, Compression, PackWriter};
+
+    #[test]
+    fn round_trips_compressed_and_stored_files() {
+        let path = std::env::temp_dir().join(format!("adze_pack_{}.pack", std::process::id()));
+        let text = "tile ".repeat(100);
+
+        let mut writer = PackWriter::create(&path).unwrap();
+        writer.add("levels\\one.txt", text.as_bytes(), Compression::Deflate).unwrap();
+        writer.add("./raw.bin", &[1, 2, 3], Compression::Deflate).unwrap();
+        writer.finish().unwrap();
+
+        let pack = AssetPack::open(&path).unwrap();
+        assert_eq!(pack.get_entry("levels/one.txt").unwrap().compression, Compression::Deflate);
+        // too small to shrink, kept uncompressed
+        assert_eq!(pack.get_entry("raw.bin").unwrap().compression, Compression::None);
+        assert_eq!(pack.read("levels/one.txt").unwrap(), text.as_bytes());
+        assert_eq!(pack.read("raw.bin").unwrap(), vec![1, 2, 3]);
+        assert!(pack.read("missing.txt").is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files_inflating_past_their_size_are_rejected() {
+        let path = std::env::temp_dir().join(format!("adze_pack_inflate_{}.pack", std::process::id()));
+        let mut writer = PackWriter::create(&path).unwrap();
+        writer.add("a.txt", "a".repeat(1000).as_bytes(), Compression::Deflate).unwrap();
+        writer.finish().unwrap();
+        let mut pack = fs::read(&path).unwrap();
+        let index_offset = u64::from_le_bytes(pack[12..20].try_into().unwrap()) as usize;
+
+        // the size after the name, offset and stored size
+        let size = index_offset + 8 + 5 + 8 + 8;
+        pack[size..size + 8].copy_from_slice(&10_u64.to_le_bytes());
+        fs::write(&path, &pack).unwrap();
+        let error = AssetPack::open(&path).unwrap().read("a.txt").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("more than its 10 bytes"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_sizes_are_rejected_before_allocating() {
+        let path = std::env::temp_dir().join(format!("adze_pack_corrupt_{}.pack", std::process::id()));
+        let mut writer = PackWriter::create(&path).unwrap();
+        writer.add("a.txt", b"a", Compression::None).unwrap();
+        writer.finish().unwrap();
+        let pack = fs::read(&path).unwrap();
+        let index_offset = u64::from_le_bytes(pack[12..20].try_into().unwrap()) as usize;
+
+        // the file count, then the length of the first name, then the first file's size
+        for field in [index_offset, index_offset + 4, index_offset + 8 + 5 + 8] {
+            let mut corrupt = pack.clone();
+            corrupt[field..field + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            fs::write(&path, &corrupt).unwrap();
+            assert_eq!(AssetPack::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
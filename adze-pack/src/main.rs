@@ -0,0 +1,69 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use adze_pack_format::{Compression, PackWriter};
+
+const USAGE: &str = "usage: adze-pack <asset folder> <output pack> [--store]
+
+Packs every file under the asset folder, keyed by its path relative to the folder.
+Files are deflate compressed unless --store is passed.";
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn pack(input: &Path, output: &Path, compression: Compression) -> io::Result<()> {
+    let mut files = vec![];
+    collect_files(input, &mut files)?;
+    // a pack built into the folder before isn't packed into the new one
+    if let Ok(output) = fs::canonicalize(output) {
+        files.retain(|file| fs::canonicalize(file).map_or(true, |file| file != output));
+    }
+    // sorted so the same folder always builds the same pack
+    files.sort();
+
+    let mut writer = PackWriter::create(output)?;
+    for file in files.iter() {
+        let relative = file.strip_prefix(input).unwrap();
+        let name = relative.to_string_lossy();
+        writer.add(&name, &fs::read(file)?, compression)?;
+    }
+
+    let mut size = 0;
+    let mut stored_size = 0;
+    for (name, entry) in writer.get_entries() {
+        println!("{:>10} -> {:>10}  {:08x}  {}", entry.size, entry.stored_size, entry.hash, name);
+        size += entry.size;
+        stored_size += entry.stored_size;
+    }
+    println!("packed {} files, {} bytes into {} bytes", writer.get_entries().len(), size, stored_size);
+
+    writer.finish()
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let store = args.iter().any(|arg| arg == "--store");
+    let paths: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+
+    if paths.len() != 2 {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let compression = if store { Compression::None } else { Compression::Deflate };
+    if let Err(err) = pack(Path::new(paths[0]), Path::new(paths[1]), compression) {
+        eprintln!("adze-pack: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,43 @@
+#type vertex
+#version 330 core
+
+layout(location = 0) in vec3 aposition;
+layout(location = 1) in vec3 anormal;
+layout(location = 2) in vec2 atexture_coordinate;
+
+layout(std140) uniform SceneData {
+    mat4 uprojection_view;
+    vec2 uresolution;
+    float utime;
+};
+
+uniform mat4 umodel;
+uniform mat3 unormal_matrix;
+
+out vec3 vnormal;
+out vec2 vtexture_coordinate;
+
+void main() {
+    vnormal = unormal_matrix * anormal;
+    vtexture_coordinate = atexture_coordinate;
+    gl_Position = uprojection_view * umodel * vec4(aposition, 1.0);
+}
+
+#type fragment
+#version 330 core
+layout(location = 0) out vec4 color;
+
+in vec3 vnormal;
+in vec2 vtexture_coordinate;
+
+uniform sampler2D utexture;
+uniform vec4 ucolor;
+// the direction the light shines in
+uniform vec3 ulight_direction;
+uniform float uambient;
+
+void main() {
+    float diffuse = max(dot(normalize(vnormal), -ulight_direction), 0.0);
+    vec4 albedo = texture(utexture, vtexture_coordinate) * ucolor;
+    color = vec4(albedo.rgb * (uambient + (1.0 - uambient) * diffuse), albedo.a);
+}
@@ -0,0 +1,38 @@
+#type vertex
+#version 330 core
+
+layout(location = 0) in vec3 aposition;
+layout(location = 1) in vec4 acolor;
+layout(location = 2) in vec2 atexture_coordinate;
+layout(location = 3) in float atexture_index;
+
+layout(std140) uniform SceneData {
+    mat4 uprojection_view;
+    vec2 uresolution;
+    float utime;
+};
+
+out vec2 vtexture_coordinate;
+out vec4 vcolor;
+out float vtexture_index;
+
+void main() {
+    vtexture_coordinate = atexture_coordinate;
+    vtexture_index = atexture_index;
+    vcolor = acolor;
+    gl_Position = uprojection_view * vec4(aposition, 1.0);
+}
+
+#type fragment
+#version 330 core
+layout(location = 0) out vec4 color;
+
+in vec4 vcolor;
+in vec2 vtexture_coordinate;
+in float vtexture_index;
+
+uniform sampler2D utextures[16];
+
+void main() {
+    color = texture(utextures[int(vtexture_index)], vtexture_coordinate) * vcolor;
+}
@@ -1,6 +1,7 @@
 
+use crate::asset::AssetEvent;
 use crate::renderer::Renderer;
-use glutin::event::VirtualKeyCode;
+use glutin::event::{MouseButton, VirtualKeyCode};
 use crate::glm::Vec2;
 use egui_glow::EguiGlow;
 
@@ -10,6 +11,7 @@ pub trait EventListener {
     fn on_ui_update(&mut self, _egui: &EguiGlow) {}
     fn on_window_closed(&mut self) {}
     fn on_window_resize(&mut self, _width: u32, _height: u32) {}
+    fn on_asset_event(&mut self, _event: &AssetEvent) {}
     fn on_key_press(&mut self, _button: VirtualKeyCode, _repeat: bool) -> bool {
         false
     }
@@ -19,12 +21,13 @@ pub trait EventListener {
     fn on_char_written(&mut self, _which: char) -> bool {
         false
     }
-    fn on_mouse_press(&mut self, _button: VirtualKeyCode) -> bool {
+    fn on_mouse_press(&mut self, _button: MouseButton) -> bool {
         false
     }
-    fn on_mouse_release(&mut self, _button: VirtualKeyCode) -> bool {
+    fn on_mouse_release(&mut self, _button: MouseButton) -> bool {
         false
     }
+    /// `position` is in pixels from the top left of the window.
     fn on_mouse_move(&mut self, _position: Vec2) -> bool {
         false
     }
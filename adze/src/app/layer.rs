@@ -34,6 +34,11 @@ impl LayerStack {
             layer.on_detach();
         }
     }
+    pub fn clear(&mut self) {
+        for mut layer in self.layers.drain(..) {
+            layer.on_detach();
+        }
+    }
     pub fn iter_mut(&mut self) -> IterMut<Box<dyn Layer>> {
         self.layers.iter_mut()
     }
@@ -1,17 +1,27 @@
 use std::str;
 use std::time::Instant;
 
-use glutin::event::{DeviceEvent, VirtualKeyCode};
+use glutin::event::{DeviceEvent, MouseScrollDelta, VirtualKeyCode, WindowEvent};
 
 use crate::app::layer::{Layer, LayerStack};
+use crate::asset::AssetServer;
+use crate::glm::{self, Vec2};
 use crate::glutin::event::ElementState;
-use crate::renderer::Renderer;
+use crate::renderer::{Renderer, RendererSettings};
+use crate::vfs::Vfs;
 
 pub mod event;
 pub mod layer;
 pub mod timer;
 
 pub static mut KEY_PRESSED: [bool; 149] = [false; 149];
+pub static mut MOUSE_POSITION: [f32; 2] = [0.0, 0.0];
+
+/// Seconds between calls to `EventListener::on_tick`.
+pub const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
+
+// touchpads scroll in pixels, layers get the same units as a mouse wheel
+const PIXELS_PER_SCROLL_LINE: f64 = 20.0;
 
 fn create_display(
     event_loop: &glutin::event_loop::EventLoop<()>,
@@ -30,7 +40,7 @@ fn create_display(
 
     let gl_window = unsafe {
         glutin::ContextBuilder::new()
-            .with_depth_buffer(0)
+            .with_depth_buffer(24)
             .with_srgb(true)
             .with_stencil_buffer(0)
             .with_vsync(true)
@@ -52,17 +62,33 @@ fn create_display(
 
 pub struct App {
     title: String,
-    layer_stack: LayerStack
+    layer_stack: LayerStack,
+    renderer_settings: RendererSettings,
+    asset_server: AssetServer
 }
 
 impl App {
     pub fn new(title: &str) -> App {
+        let asset_server = AssetServer::new();
+        asset_server.set_vfs(Vfs::with_defaults(title));
+
         App {
             title: String::from(title),
-            layer_stack: LayerStack::new()
+            layer_stack: LayerStack::new(),
+            renderer_settings: RendererSettings::default(),
+            asset_server
         }
     }
 
+    pub fn set_renderer_settings(&mut self, renderer_settings: RendererSettings) {
+        self.renderer_settings = renderer_settings;
+    }
+
+    /// The assets shared by the app's layers, the renderer and the editor.
+    pub fn get_asset_server(&self) -> AssetServer {
+        self.asset_server.clone()
+    }
+
     pub fn push_layer(&mut self, layer: Box<dyn Layer>) {
         self.layer_stack.push_layer(layer);
     }
@@ -71,6 +97,11 @@ impl App {
         unsafe { return KEY_PRESSED [key_code as usize]; }
     }
 
+    /// The cursor position in pixels from the top left of the window.
+    pub fn get_mouse_position() -> Vec2 {
+        unsafe { glm::vec2(MOUSE_POSITION[0], MOUSE_POSITION[1]) }
+    }
+
     pub fn run(self) {
         let event_loop = glutin::event_loop::EventLoop::with_user_event();
 
@@ -78,11 +109,16 @@ impl App {
 
         let mut egui = egui_glow::EguiGlow::new(&gl_window, &gl);
 
-        let mut renderer = Renderer::new(gl);
+        let mut renderer = Renderer::new(gl, self.renderer_settings, self.asset_server);
+        let size = gl_window.window().inner_size();
+        renderer.set_viewport(0, 0, size.width, size.height);
+        let mut renderer = Some(renderer);
         let mut layer_stack = self.layer_stack;
+        for layer in layer_stack.iter_mut() {
+            layer.on_window_resize(size.width, size.height);
+        }
 
         let clock = Instant::now();
-        let fixed_timestep = 1.0 / 60.0;
         let mut elapsed_time = 0.0;
         let mut dt = clock.elapsed().as_secs_f32() - elapsed_time;
 
@@ -92,6 +128,17 @@ impl App {
 
             *control_flow = ControlFlow::Wait;
 
+            if let Event::LoopDestroyed = event {
+                // layers are dropped first so the renderer only reports resources that leaked
+                if let Some(renderer) = renderer.take() {
+                    egui.destroy(renderer.borrow_context());
+                    layer_stack.clear();
+                    renderer.get_asset_server().clear();
+                }
+                return;
+            }
+            let renderer = renderer.as_mut().unwrap();
+
             let mut redraw = || {
                 egui.begin_frame(gl_window.window());
 
@@ -104,14 +151,22 @@ impl App {
                 {
                     // draw things behind egui here
                     dt = clock.elapsed().as_secs_f32() - elapsed_time;
-                    while dt >= fixed_timestep {
-                        dt -= fixed_timestep;
-                        elapsed_time += fixed_timestep;
+                    while dt >= FIXED_TIMESTEP {
+                        dt -= FIXED_TIMESTEP;
+                        elapsed_time += FIXED_TIMESTEP;
+                        renderer.set_time(elapsed_time);
+
+                        for layer in layer_stack.iter_mut().rev() {
+                            layer.on_tick(renderer);
+                        }
+                    }
 
+                    for event in renderer.get_asset_server().take_events() {
                         for layer in layer_stack.iter_mut().rev() {
-                            layer.on_tick(&mut renderer);
+                            layer.on_asset_event(&event);
                         }
                     }
+                    renderer.present();
                     egui.paint(&gl_window, renderer.borrow_context(), shapes);
 
                     // draw things on top of egui here
@@ -152,8 +207,43 @@ impl App {
                         *control_flow = glutin::event_loop::ControlFlow::Exit;
                     }
 
-                    if let glutin::event::WindowEvent::Resized(physical_size) = event {
-                        gl_window.resize(physical_size);
+                    match event {
+                        WindowEvent::Resized(physical_size) => {
+                            gl_window.resize(physical_size);
+                            renderer.set_viewport(0, 0, physical_size.width, physical_size.height);
+                            for layer in layer_stack.iter_mut().rev() {
+                                layer.on_window_resize(physical_size.width, physical_size.height);
+                            }
+                        }
+                        WindowEvent::CursorMoved { position, .. } => {
+                            let position = glm::vec2(position.x as f32, position.y as f32);
+                            unsafe { MOUSE_POSITION = [position.x, position.y]; }
+                            for layer in layer_stack.iter_mut().rev() {
+                                layer.on_mouse_move(position);
+                            }
+                        }
+                        WindowEvent::MouseWheel { delta, .. } => {
+                            let delta = match delta {
+                                MouseScrollDelta::LineDelta(x, y) => glm::vec2(x, y),
+                                MouseScrollDelta::PixelDelta(pixels) => glm::vec2(
+                                    (pixels.x / PIXELS_PER_SCROLL_LINE) as f32,
+                                    (pixels.y / PIXELS_PER_SCROLL_LINE) as f32
+                                )
+                            };
+                            for layer in layer_stack.iter_mut().rev() {
+                                layer.on_mouse_scroll(delta);
+                            }
+                        }
+                        WindowEvent::MouseInput { state, button, .. } => {
+                            for layer in layer_stack.iter_mut().rev() {
+                                if state == ElementState::Pressed {
+                                    layer.on_mouse_press(button);
+                                } else {
+                                    layer.on_mouse_release(button);
+                                }
+                            }
+                        }
+                        _ => {}
                     }
 
                     egui.on_event(&event);
@@ -163,9 +253,6 @@ impl App {
                 Event::RedrawRequested(_) => {
                     //gl_window.swap_buffers().unwrap();
                 },
-                Event::LoopDestroyed => {
-                    egui.destroy(&renderer.borrow_context());
-                }
                 _ => (),
             }
         });
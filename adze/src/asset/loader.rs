@@ -0,0 +1,151 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::asset::AssetId;
+use crate::renderer::mesh::MeshData;
+use crate::renderer::texture::ImageData;
+use crate::vfs::Vfs;
+
+const MAX_WORKERS: usize = 4;
+
+pub(crate) enum LoadKind {
+    Image { premultiply_alpha: bool },
+    Text,
+    Mesh
+}
+
+pub(crate) struct LoadJob {
+    pub(crate) id: AssetId,
+    pub(crate) path: String,
+    pub(crate) kind: LoadKind,
+    pub(crate) vfs: Arc<Vfs>
+}
+
+pub(crate) enum LoadedData {
+    Image(ImageData),
+    Text(String),
+    Mesh(MeshData)
+}
+
+pub(crate) struct LoadResult {
+    pub(crate) id: AssetId,
+    pub(crate) data: Result<LoadedData, String>
+}
+
+fn run_job(job: LoadJob) -> LoadResult {
+    let data = match job.kind {
+        LoadKind::Image { premultiply_alpha } => job.vfs.read(&job.path)
+            .map_err(|err| format!("Could not read {}: {}", job.path, err))
+            .and_then(|bytes| ImageData::from_bytes(&job.path, &bytes, premultiply_alpha).map_err(|err| err.to_string()))
+            .map(LoadedData::Image),
+        LoadKind::Text => job.vfs.read_to_string(&job.path)
+            .map(LoadedData::Text)
+            .map_err(|err| format!("Could not read {}: {}", job.path, err)),
+        LoadKind::Mesh => job.vfs.read_to_string(&job.path)
+            .map_err(|err| format!("Could not read {}: {}", job.path, err))
+            .and_then(|source| MeshData::from_obj(&source).map_err(|err| format!("Could not load mesh {}: {}", job.path, err)))
+            .map(LoadedData::Mesh)
+    };
+
+    LoadResult {
+        id: job.id,
+        data
+    }
+}
+
+/// Reads and decodes files on worker threads. Anything that touches the context is left to
+/// the main thread, which collects the results with `try_receive` or `receive`.
+pub(crate) struct Loader {
+    jobs: Option<Sender<LoadJob>>,
+    results: Receiver<LoadResult>,
+    workers: Vec<JoinHandle<()>>
+}
+
+impl Loader {
+    pub(crate) fn new() -> Loader {
+        let (job_sender, job_receiver) = mpsc::channel::<LoadJob>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let worker_count = thread::available_parallelism().map_or(1, |count| count.get()).min(MAX_WORKERS);
+        let workers = (0..worker_count)
+            .map(|i| {
+                let jobs = job_receiver.clone();
+                let results: Sender<LoadResult> = result_sender.clone();
+                thread::Builder::new()
+                    .name(format!("asset loader {}", i))
+                    .spawn(move || loop {
+                        // the lock is released as soon as a job is taken
+                        let job = jobs.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => if results.send(run_job(job)).is_err() {
+                                break;
+                            },
+                            Err(_) => break
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        Loader {
+            jobs: Some(job_sender),
+            results,
+            workers
+        }
+    }
+
+    pub(crate) fn submit(&self, job: LoadJob) {
+        if let Some(jobs) = &self.jobs {
+            jobs.send(job).unwrap();
+        }
+    }
+
+    pub(crate) fn try_receive(&self) -> Option<LoadResult> {
+        self.results.try_recv().ok()
+    }
+
+    /// Blocks until a worker finishes a job.
+    pub(crate) fn receive(&self) -> Option<LoadResult> {
+        self.results.recv().ok()
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        // closing the channel stops the workers once they finish their current job
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use crate::asset::loader::{LoadJob, LoadKind, LoadedData, Loader};
+    use crate::vfs::{MountSource, Vfs};
+
+    #[test]
+    fn decodes_on_worker_threads() {
+        let loader = Loader::new();
+        let mut vfs = Vfs::new();
+        vfs.mount("assets", MountSource::Directory(PathBuf::from("../sandbox/assets")));
+        let vfs = Arc::new(vfs);
+        loader.submit(LoadJob { id: 1, path: "textures/Checkerboard.png".to_string(), kind: LoadKind::Image { premultiply_alpha: false }, vfs: vfs.clone() });
+        loader.submit(LoadJob { id: 2, path: "missing.txt".to_string(), kind: LoadKind::Text, vfs });
+
+        let mut results: Vec<_> = (0..2).map(|_| loader.receive().unwrap()).collect();
+        results.sort_by_key(|result| result.id);
+
+        match &results[0].data {
+            Ok(LoadedData::Image(image)) => assert!(image.width > 0 && image.height > 0),
+            _ => panic!("the checkerboard should decode")
+        }
+        assert!(results[1].data.is_err());
+    }
+}
@@ -0,0 +1,660 @@
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+
+use crate::asset::loader::{LoadJob, LoadKind, LoadResult, LoadedData, Loader};
+use crate::asset::watcher::FileWatcher;
+use crate::renderer::mesh::Mesh;
+use crate::renderer::shader::Shader;
+use crate::renderer::texture::{SamplerDesc, Texture};
+use crate::vfs::{MountSource, Vfs};
+
+mod loader;
+mod watcher;
+
+pub type AssetId = u64;
+
+/// A reference to an asset owned by an `AssetServer`. Handles are counted, the asset is
+/// unloaded on the next `AssetServer::update` after the last handle to it is dropped.
+pub struct Handle<T> {
+    id: Rc<AssetId>,
+    marker: PhantomData<fn() -> T>
+}
+
+impl<T> Handle<T> {
+    fn new(id: Rc<AssetId>) -> Handle<T> {
+        Handle {
+            id,
+            marker: PhantomData
+        }
+    }
+
+    pub fn get_id(&self) -> AssetId {
+        *self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle::new(self.id.clone())
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get_id() == other.get_id()
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get_id().hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.get_id())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    /// The file is being read and decoded in the background.
+    Loading,
+    Loaded,
+    /// Loading failed, textures are replaced by the renderer's placeholder.
+    Failed(String)
+}
+
+/// A set of assets to wait on together, like everything a level needs.
+#[derive(Clone, Debug, Default)]
+pub struct AssetGroup {
+    ids: Vec<AssetId>
+}
+
+impl AssetGroup {
+    pub fn new() -> AssetGroup {
+        AssetGroup {
+            ids: vec![]
+        }
+    }
+
+    pub fn add<T>(&mut self, handle: &Handle<T>) {
+        self.ids.push(handle.get_id());
+    }
+
+    pub fn with<T>(mut self, handle: &Handle<T>) -> AssetGroup {
+        self.add(handle);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AssetKind {
+    Texture,
+    Shader,
+    Text,
+    Mesh
+}
+
+/// Sent to layers through `EventListener::on_asset_event` when a hot reloaded file has been
+/// loaded again.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetEvent {
+    Reloaded { id: AssetId, kind: AssetKind, path: String },
+    /// The changed file couldn't be loaded, the asset keeps its previous contents.
+    ReloadFailed { id: AssetId, kind: AssetKind, path: String, error: String }
+}
+
+/// What the editor shows about a loaded asset.
+#[derive(Clone, Debug)]
+pub struct AssetInfo {
+    pub id: AssetId,
+    pub kind: AssetKind,
+    pub path: String,
+    pub state: LoadState,
+    pub handle_count: usize
+}
+
+struct AssetEntry<T> {
+    path: String,
+    // None until the asset is created once its data has loaded
+    asset: Option<T>,
+    state: LoadState,
+    handle: Weak<AssetId>
+}
+
+impl<T> AssetEntry<T> {
+    /// Whether the asset has been loaded before and its file is being loaded again.
+    fn is_reloading(&self) -> bool {
+        self.state != LoadState::Loading
+    }
+
+    /// Records how a load went, returning the event to send if it was a reload.
+    fn complete(&mut self, id: AssetId, kind: AssetKind, result: Result<(), String>) -> Option<AssetEvent> {
+        let reloading = self.is_reloading();
+        let path = self.path.clone();
+        match result {
+            Ok(()) => {
+                self.state = LoadState::Loaded;
+                if reloading {
+                    Some(AssetEvent::Reloaded { id, kind, path })
+                } else {
+                    None
+                }
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                if reloading {
+                    Some(AssetEvent::ReloadFailed { id, kind, path, error })
+                } else {
+                    self.state = LoadState::Failed(error);
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// The assets of one type, indexed by id and by the path they were loaded from.
+struct Assets<T> {
+    entries: HashMap<AssetId, AssetEntry<T>>,
+    ids: HashMap<String, AssetId>
+}
+
+impl<T> Assets<T> {
+    fn new() -> Assets<T> {
+        Assets {
+            entries: HashMap::new(),
+            ids: HashMap::new()
+        }
+    }
+
+    /// Returns a handle to the asset loaded from `path`, reviving it if its last handle was
+    /// dropped but it hasn't been unloaded yet.
+    fn find(&mut self, path: &str) -> Option<Handle<T>> {
+        let id = *self.ids.get(path)?;
+        let entry = self.entries.get_mut(&id)?;
+        let handle = entry.handle.upgrade().unwrap_or_else(|| {
+            let handle = Rc::new(id);
+            entry.handle = Rc::downgrade(&handle);
+            handle
+        });
+        Some(Handle::new(handle))
+    }
+
+    fn insert(&mut self, id: AssetId, path: &str, asset: Option<T>) -> Handle<T> {
+        let handle = Rc::new(id);
+        self.entries.insert(id, AssetEntry {
+            path: path.to_string(),
+            asset,
+            state: LoadState::Loading,
+            handle: Rc::downgrade(&handle)
+        });
+        self.ids.insert(path.to_string(), id);
+        Handle::new(handle)
+    }
+
+    fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.entries.get(&handle.get_id())?.asset.as_ref()
+    }
+
+    fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
+        self.entries.get_mut(&handle.get_id())?.asset.as_mut()
+    }
+
+    fn get_state(&self, id: AssetId) -> Option<&LoadState> {
+        self.entries.get(&id).map(|entry| &entry.state)
+    }
+
+    /// Drops every asset nobody holds a handle to anymore, returning their ids.
+    fn remove_unused(&mut self) -> Vec<AssetId> {
+        let ids = &mut self.ids;
+        let mut removed = vec![];
+        self.entries.retain(|id, entry| {
+            let used = entry.handle.strong_count() > 0;
+            if !used {
+                ids.remove(&entry.path);
+                removed.push(*id);
+            }
+            used
+        });
+        removed
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.ids.clear();
+    }
+
+    fn infos(&self, kind: AssetKind) -> impl Iterator<Item = AssetInfo> + '_ {
+        self.entries.iter().map(move |(id, entry)| AssetInfo {
+            id: *id,
+            kind,
+            path: entry.path.clone(),
+            state: entry.state.clone(),
+            handle_count: entry.handle.strong_count()
+        })
+    }
+}
+
+struct AssetStorage {
+    next_id: AssetId,
+    textures: Assets<Texture>,
+    shaders: Assets<Shader>,
+    texts: Assets<String>,
+    meshes: Assets<Mesh>,
+    missing: Vec<String>,
+    premultiply_alpha: bool,
+    vfs: Arc<Vfs>,
+    loader: Loader,
+    watcher: FileWatcher,
+    hot_reload: bool,
+    events: Vec<AssetEvent>
+}
+
+impl AssetStorage {
+    fn next_id(&mut self) -> AssetId {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn check_exists(&mut self, path: &str) {
+        if !self.vfs.exists(path) && !self.missing.iter().any(|missing| missing == path) {
+            eprintln!("Asset not found: {}", path);
+            self.missing.push(path.to_string());
+        }
+    }
+
+    fn get_state(&self, id: AssetId) -> Option<&LoadState> {
+        self.textures.get_state(id)
+            .or_else(|| self.shaders.get_state(id))
+            .or_else(|| self.texts.get_state(id))
+            .or_else(|| self.meshes.get_state(id))
+    }
+
+    /// Reads the file in the background and watches it for changes if it is a loose file.
+    fn start_load(&mut self, id: AssetId, path: &str, kind: LoadKind) {
+        self.loader.submit(LoadJob {
+            id,
+            path: path.to_string(),
+            kind,
+            vfs: self.vfs.clone()
+        });
+        if let Some(loose_path) = self.vfs.get_loose_path(path) {
+            self.watcher.watch(id, loose_path);
+        }
+    }
+
+    /// Loads a changed file again, the asset is replaced once it is ready.
+    fn reload(&mut self, id: AssetId) {
+        let job = if let Some(entry) = self.textures.entries.get(&id) {
+            let premultiply_alpha = entry.asset.as_ref().is_some_and(|texture| texture.get_premultiply_alpha());
+            LoadJob { id, path: entry.path.clone(), kind: LoadKind::Image { premultiply_alpha }, vfs: self.vfs.clone() }
+        } else if let Some(entry) = self.shaders.entries.get(&id) {
+            LoadJob { id, path: entry.path.clone(), kind: LoadKind::Text, vfs: self.vfs.clone() }
+        } else if let Some(entry) = self.texts.entries.get(&id) {
+            LoadJob { id, path: entry.path.clone(), kind: LoadKind::Text, vfs: self.vfs.clone() }
+        } else if let Some(entry) = self.meshes.entries.get(&id) {
+            LoadJob { id, path: entry.path.clone(), kind: LoadKind::Mesh, vfs: self.vfs.clone() }
+        } else {
+            return;
+        };
+        self.loader.submit(job);
+    }
+
+    /// Creates the asset a worker finished loading, or updates it in place when it was
+    /// reloaded. Results for assets unloaded while they were loading are dropped.
+    fn finish(&mut self, gl: &glow::Context, result: LoadResult) {
+        let id = result.id;
+        let event = if let Some(entry) = self.textures.entries.get_mut(&id) {
+            let reloading = entry.is_reloading();
+            let texture = entry.asset.as_mut().unwrap();
+            let loaded = match result.data {
+                Ok(LoadedData::Image(image)) => {
+                    if reloading {
+                        texture.reload(gl, image);
+                    } else {
+                        texture.upload(gl, image);
+                    }
+                    Ok(())
+                }
+                Ok(_) => unreachable!("textures are loaded as images"),
+                Err(err) => {
+                    if !reloading {
+                        texture.load_placeholder(gl);
+                    }
+                    Err(err)
+                }
+            };
+            entry.complete(id, AssetKind::Texture, loaded)
+        } else if let Some(entry) = self.shaders.entries.get_mut(&id) {
+            let loaded = match result.data {
+                Ok(LoadedData::Text(source)) => match Shader::from_source(gl, &source) {
+                    Ok(shader) => {
+                        entry.asset = Some(shader);
+                        Ok(())
+                    }
+                    Err(err) => Err(format!("Could not load shader {}: {}", entry.path, err))
+                },
+                Ok(_) => unreachable!("shaders are loaded as text"),
+                Err(err) => Err(err)
+            };
+            entry.complete(id, AssetKind::Shader, loaded)
+        } else if let Some(entry) = self.texts.entries.get_mut(&id) {
+            let loaded = match result.data {
+                Ok(LoadedData::Text(text)) => {
+                    entry.asset = Some(text);
+                    Ok(())
+                }
+                Ok(_) => unreachable!("text is loaded as text"),
+                Err(err) => Err(err)
+            };
+            entry.complete(id, AssetKind::Text, loaded)
+        } else if let Some(entry) = self.meshes.entries.get_mut(&id) {
+            let loaded = match result.data {
+                Ok(LoadedData::Mesh(data)) => {
+                    entry.asset = Some(Mesh::new(gl, &data));
+                    Ok(())
+                }
+                Ok(_) => unreachable!("meshes are loaded as meshes"),
+                Err(err) => Err(err)
+            };
+            entry.complete(id, AssetKind::Mesh, loaded)
+        } else {
+            None
+        };
+        self.events.extend(event);
+    }
+}
+
+/// Loads textures, shaders, meshes and text files once per path and hands out counted `Handle`s to
+/// them. With hot reloading on, files changed on disk are loaded again and replace the assets
+/// in place.
+///
+/// The server is a shared reference, clones of it all see the same assets. The app creates
+/// one before it runs, layers get theirs from `App::get_asset_server` and the renderer
+/// creates pending assets and unloads unused ones every `Renderer::begin`.
+#[derive(Clone)]
+pub struct AssetServer {
+    storage: Rc<RefCell<AssetStorage>>
+}
+
+impl AssetServer {
+    pub fn new() -> AssetServer {
+        AssetServer {
+            storage: Rc::new(RefCell::new(AssetStorage {
+                next_id: 0,
+                textures: Assets::new(),
+                shaders: Assets::new(),
+                texts: Assets::new(),
+                meshes: Assets::new(),
+                missing: vec![],
+                premultiply_alpha: false,
+                vfs: Arc::new(Vfs::default()),
+                loader: Loader::new(),
+                watcher: FileWatcher::new(),
+                hot_reload: cfg!(debug_assertions),
+                events: vec![]
+            }))
+        }
+    }
+
+    /// Returns a handle to the texture at `path` and starts decoding it in the background. A
+    /// texture already loaded from the same path is shared, along with the sampler it was
+    /// first loaded with.
+    pub fn load_texture(&self, path: &str, sampler: SamplerDesc) -> Handle<Texture> {
+        let mut storage = self.storage.borrow_mut();
+        if let Some(handle) = storage.textures.find(path) {
+            return handle;
+        }
+
+        storage.check_exists(path);
+        let mut texture = Texture::new(path.to_string(), 1.0, sampler);
+        texture.set_premultiply_alpha(storage.premultiply_alpha);
+        let id = storage.next_id();
+        let premultiply_alpha = storage.premultiply_alpha;
+        storage.start_load(id, path, LoadKind::Image { premultiply_alpha });
+        storage.textures.insert(id, path, Some(texture))
+    }
+
+    /// Returns a handle to the shader at `path`, a single file split into stages with
+    /// `#type vertex` and `#type fragment` lines. The file is read in the background and
+    /// compiled by the first update after that.
+    pub fn load_shader(&self, path: &str) -> Handle<Shader> {
+        let mut storage = self.storage.borrow_mut();
+        if let Some(handle) = storage.shaders.find(path) {
+            return handle;
+        }
+
+        storage.check_exists(path);
+        let id = storage.next_id();
+        storage.start_load(id, path, LoadKind::Text);
+        storage.shaders.insert(id, path, None)
+    }
+
+    /// Returns a handle to the contents of a text file, for data like levels or dialogue.
+    pub fn load_text(&self, path: &str) -> Handle<String> {
+        let mut storage = self.storage.borrow_mut();
+        if let Some(handle) = storage.texts.find(path) {
+            return handle;
+        }
+
+        storage.check_exists(path);
+        let id = storage.next_id();
+        storage.start_load(id, path, LoadKind::Text);
+        storage.texts.insert(id, path, None)
+    }
+
+    /// Returns a handle to the mesh in the Wavefront OBJ file at `path`. The file is parsed in
+    /// the background and uploaded by the first update after that.
+    pub fn load_mesh(&self, path: &str) -> Handle<Mesh> {
+        let mut storage = self.storage.borrow_mut();
+        if let Some(handle) = storage.meshes.find(path) {
+            return handle;
+        }
+
+        storage.check_exists(path);
+        let id = storage.next_id();
+        storage.start_load(id, path, LoadKind::Mesh);
+        storage.meshes.insert(id, path, None)
+    }
+
+    /// The load state of any asset, `None` once it has been unloaded.
+    pub fn get_load_state<T>(&self, handle: &Handle<T>) -> Option<LoadState> {
+        self.storage.borrow().get_state(handle.get_id()).cloned()
+    }
+
+    pub fn is_loaded<T>(&self, handle: &Handle<T>) -> bool {
+        self.get_load_state(handle) == Some(LoadState::Loaded)
+    }
+
+    /// The fraction of the group's assets that are done loading, failed ones included.
+    pub fn get_group_progress(&self, group: &AssetGroup) -> f32 {
+        if group.is_empty() {
+            return 1.0;
+        }
+
+        let storage = self.storage.borrow();
+        let done = group.ids.iter()
+            .filter(|id| storage.get_state(**id) != Some(&LoadState::Loading))
+            .count();
+        done as f32 / group.len() as f32
+    }
+
+    pub fn is_group_loaded(&self, group: &AssetGroup) -> bool {
+        self.get_group_progress(group) >= 1.0
+    }
+
+    /// Blocks until every asset in the group has loaded or failed, creating them as they
+    /// come in. Meant for level loads, where stalling is better than drawing half a level.
+    pub fn wait_for_group(&self, gl: &glow::Context, group: &AssetGroup) {
+        let mut storage = self.storage.borrow_mut();
+        while group.ids.iter().any(|id| storage.get_state(*id) == Some(&LoadState::Loading)) {
+            match storage.loader.receive() {
+                Some(result) => storage.finish(gl, result),
+                None => break
+            }
+        }
+    }
+
+    pub fn get_texture(&self, handle: &Handle<Texture>) -> Option<Ref<'_, Texture>> {
+        Ref::filter_map(self.storage.borrow(), |storage| storage.textures.get(handle)).ok()
+    }
+
+    pub fn get_texture_mut(&self, handle: &Handle<Texture>) -> Option<RefMut<'_, Texture>> {
+        RefMut::filter_map(self.storage.borrow_mut(), |storage| storage.textures.get_mut(handle)).ok()
+    }
+
+    /// Returns the shader once it has been compiled, shaders that failed to load stay `None`.
+    pub fn get_shader(&self, handle: &Handle<Shader>) -> Option<Ref<'_, Shader>> {
+        Ref::filter_map(self.storage.borrow(), |storage| storage.shaders.get(handle)).ok()
+    }
+
+    pub fn get_text(&self, handle: &Handle<String>) -> Option<Ref<'_, String>> {
+        Ref::filter_map(self.storage.borrow(), |storage| storage.texts.get(handle)).ok()
+    }
+
+    pub fn get_mesh(&self, handle: &Handle<Mesh>) -> Option<Ref<'_, Mesh>> {
+        Ref::filter_map(self.storage.borrow(), |storage| storage.meshes.get(handle)).ok()
+    }
+
+    /// Where assets are read from from now on, paths passed to the load functions are
+    /// looked up in it. The app starts with `Vfs::with_defaults`.
+    pub fn set_vfs(&self, vfs: Vfs) {
+        self.storage.borrow_mut().vfs = Arc::new(vfs);
+    }
+
+    /// Adds a source to one of the vfs' mount points, see `Vfs::mount`.
+    pub fn mount(&self, mount_point: &str, source: MountSource) {
+        Arc::make_mut(&mut self.storage.borrow_mut().vfs).mount(mount_point, source);
+    }
+
+    pub fn get_vfs(&self) -> Arc<Vfs> {
+        self.storage.borrow().vfs.clone()
+    }
+
+    /// Whether changed files are loaded again on update, on by default in debug builds.
+    pub fn set_hot_reload(&self, hot_reload: bool) {
+        self.storage.borrow_mut().hot_reload = hot_reload;
+    }
+
+    pub fn get_hot_reload(&self) -> bool {
+        self.storage.borrow().hot_reload
+    }
+
+    /// Takes the reload events since the last call. The app hands these to its layers after
+    /// every frame.
+    pub fn take_events(&self) -> Vec<AssetEvent> {
+        std::mem::take(&mut self.storage.borrow_mut().events)
+    }
+
+    /// Whether textures loaded from now on have their color multiplied by alpha. The renderer
+    /// sets this to match `RendererSettings::premultiplied_alpha`.
+    pub fn set_premultiply_alpha(&self, premultiply_alpha: bool) {
+        self.storage.borrow_mut().premultiply_alpha = premultiply_alpha;
+    }
+
+    /// Paths that were requested but didn't exist when they were loaded.
+    pub fn get_missing_paths(&self) -> Vec<String> {
+        self.storage.borrow().missing.clone()
+    }
+
+    pub fn get_asset_infos(&self) -> Vec<AssetInfo> {
+        let storage = self.storage.borrow();
+        let mut infos: Vec<AssetInfo> = storage.textures.infos(AssetKind::Texture)
+            .chain(storage.shaders.infos(AssetKind::Shader))
+            .chain(storage.texts.infos(AssetKind::Text))
+            .chain(storage.meshes.infos(AssetKind::Mesh))
+            .collect();
+        infos.sort_by_key(|info| info.id);
+        infos
+    }
+
+    /// Uploads and compiles assets that finished loading since the last update, unloads the
+    /// ones without any handles left and starts reloading changed files.
+    pub fn update(&self, gl: &glow::Context) {
+        let mut storage = self.storage.borrow_mut();
+        let mut removed = storage.textures.remove_unused();
+        removed.extend(storage.shaders.remove_unused());
+        removed.extend(storage.texts.remove_unused());
+        removed.extend(storage.meshes.remove_unused());
+        for id in removed {
+            storage.watcher.unwatch(id);
+        }
+
+        if storage.hot_reload {
+            for id in storage.watcher.poll() {
+                storage.reload(id);
+            }
+        }
+
+        while let Some(result) = storage.loader.try_receive() {
+            storage.finish(gl, result);
+        }
+    }
+
+    /// Unloads every asset, handles that are still alive no longer resolve. Called when the
+    /// app shuts down so assets are deleted while the context still exists.
+    pub fn clear(&self) {
+        let mut storage = self.storage.borrow_mut();
+        storage.textures.clear();
+        storage.shaders.clear();
+        storage.texts.clear();
+        storage.meshes.clear();
+        storage.watcher.clear();
+    }
+}
+
+impl Default for AssetServer {
+    fn default() -> Self {
+        AssetServer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asset::AssetServer;
+    use crate::renderer::texture::SamplerDesc;
+
+    #[test]
+    fn handles_are_shared_per_path_and_counted() {
+        let assets = AssetServer::new();
+        let first = assets.load_texture("missing/a.png", SamplerDesc::default());
+        let second = assets.load_texture("missing/a.png", SamplerDesc::linear());
+        let other = assets.load_texture("missing/b.png", SamplerDesc::default());
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(assets.get_missing_paths(), vec!["missing/a.png", "missing/b.png"]);
+        assert_eq!(assets.get_asset_infos()[0].handle_count, 2);
+
+        drop(first);
+        drop(other);
+        assets.storage.borrow_mut().textures.remove_unused();
+        assert!(assets.get_texture(&second).is_some());
+        assert_eq!(assets.get_asset_infos().len(), 1);
+
+        let id = second.get_id();
+        drop(second);
+        // loading again before the update that would unload it keeps the same asset
+        assert_eq!(assets.load_texture("missing/a.png", SamplerDesc::default()).get_id(), id);
+        assets.storage.borrow_mut().textures.remove_unused();
+        assert!(assets.get_asset_infos().is_empty());
+    }
+}
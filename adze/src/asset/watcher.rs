@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::asset::AssetId;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Notices changes to asset files by polling their modification times, which works the
+/// same on every platform and is cheap for the handful of files a game has loaded.
+pub(crate) struct FileWatcher {
+    files: HashMap<AssetId, (PathBuf, Option<SystemTime>)>,
+    last_poll: Instant
+}
+
+impl FileWatcher {
+    pub(crate) fn new() -> FileWatcher {
+        FileWatcher {
+            files: HashMap::new(),
+            last_poll: Instant::now()
+        }
+    }
+
+    pub(crate) fn watch(&mut self, id: AssetId, path: PathBuf) {
+        let modified = modified(&path);
+        self.files.insert(id, (path, modified));
+    }
+
+    pub(crate) fn unwatch(&mut self, id: AssetId) {
+        self.files.remove(&id);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.files.clear();
+    }
+
+    /// Returns the assets whose files were written to since the last poll. Files are checked
+    /// at most every `POLL_INTERVAL`.
+    pub(crate) fn poll(&mut self) -> Vec<AssetId> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        self.poll_now()
+    }
+
+    fn poll_now(&mut self) -> Vec<AssetId> {
+        let mut changed = vec![];
+        for (id, (path, last_modified)) in self.files.iter_mut() {
+            let modified = modified(path);
+            // a file that is being rewritten can briefly be missing, wait for it to come back
+            if modified.is_some() && modified != *last_modified {
+                *last_modified = modified;
+                changed.push(*id);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    use crate::asset::watcher::FileWatcher;
+
+    #[test]
+    fn reports_files_written_after_they_are_watched() {
+        let path = std::env::temp_dir().join(format!("adze_watcher_{}.txt", std::process::id()));
+        fs::write(&path, "first").unwrap();
+
+        let mut watcher = FileWatcher::new();
+        watcher.watch(7, path.clone());
+        assert!(watcher.poll_now().is_empty());
+
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+        assert_eq!(watcher.poll_now(), vec![7]);
+        assert!(watcher.poll_now().is_empty());
+
+        watcher.unwatch(7);
+        fs::remove_file(&path).unwrap();
+    }
+}
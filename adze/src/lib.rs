@@ -1,15 +1,21 @@
+// lets the derive macros refer to `::adze` from inside the crate too
+extern crate self as adze;
+
+pub use bytemuck;
 pub use egui;
 pub use egui_glow;
 pub use glutin;
 pub use nalgebra_glm as glm;
+pub use ron;
 
+pub mod asset;
 pub mod renderer;
+pub mod scene;
 pub mod app;
+pub mod vfs;
 
 #[cfg(test)]
 mod tests {
-    use crate::adze;
-
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
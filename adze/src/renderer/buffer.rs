@@ -1,6 +1,12 @@
+use core::marker::PhantomData;
+use core::mem;
+
+use bytemuck::Pod;
 use glow::{HasContext, Buffer};
-use crate::renderer::buffer;
-use crate::renderer::QuadVertex;
+use crate::glm::{IVec2, IVec3, IVec4, Mat3, Mat4, Vec2, Vec3, Vec4};
+use crate::renderer::resource::{self, GlResource};
+
+pub use adze_derive::Vertex;
 
 #[derive(Clone, Copy)]
 pub enum ShaderDataType {
@@ -33,7 +39,7 @@ fn get_shader_data_type_size(data_type: ShaderDataType) -> i32 {
         ShaderDataType::Float2 => 4 * 2,
         ShaderDataType::Float3 => 4 * 3,
         ShaderDataType::Float4 => 4 * 4,
-        ShaderDataType::Matrix3 => 3 * 3 * 3,
+        ShaderDataType::Matrix3 => 4 * 3 * 3,
         ShaderDataType::Matrix4 => 4 * 4 * 4,
         ShaderDataType::Integer1 => 4,
         ShaderDataType::Integer2 => 4 * 2,
@@ -71,6 +77,22 @@ impl BufferElement {
             _ => 0 // error?
         };
     }
+
+    /// Matrices take up one attribute location per column.
+    fn get_column_count(&self) -> i32 {
+        match self.data_type {
+            ShaderDataType::Matrix3 => 3,
+            ShaderDataType::Matrix4 => 4,
+            _ => 1
+        }
+    }
+
+    fn is_integer(&self) -> bool {
+        matches!(
+            self.data_type,
+            ShaderDataType::Integer1 | ShaderDataType::Integer2 | ShaderDataType::Integer3 | ShaderDataType::Integer4 | ShaderDataType::Boolean
+        )
+    }
 }
 
 fn to_opengl_type(data_type: ShaderDataType) -> u32 {
@@ -85,14 +107,48 @@ fn to_opengl_type(data_type: ShaderDataType) -> u32 {
         ShaderDataType::Integer2 => glow::INT,
         ShaderDataType::Integer3 => glow::INT,
         ShaderDataType::Integer4 => glow::INT,
-        ShaderDataType::Boolean => glow::BOOL,
+        // there are no bool attributes, they are read as bytes through the integer path
+        ShaderDataType::Boolean => glow::UNSIGNED_BYTE,
         _ => 0 // throw error?
     };
 }
 
+// https://www.khronos.org/registry/OpenGL/specs/gl/glspec45.core.pdf#page=159
+fn get_std140_alignment(data_type: ShaderDataType) -> i32 {
+    match data_type {
+        ShaderDataType::Float1 => 4,
+        ShaderDataType::Float2 => 4 * 2,
+        ShaderDataType::Float3 => 4 * 4,
+        ShaderDataType::Float4 => 4 * 4,
+        ShaderDataType::Matrix3 => 4 * 4,
+        ShaderDataType::Matrix4 => 4 * 4,
+        ShaderDataType::Integer1 => 4,
+        ShaderDataType::Integer2 => 4 * 2,
+        ShaderDataType::Integer3 => 4 * 4,
+        ShaderDataType::Integer4 => 4 * 4,
+        ShaderDataType::Boolean => 4,
+        _ => 0
+    }
+}
+
+fn get_std140_size(data_type: ShaderDataType) -> i32 {
+    match data_type {
+        // matrix columns are padded out to a vec4 each
+        ShaderDataType::Matrix3 => 3 * 4 * 4,
+        // bools take up a full machine word
+        ShaderDataType::Boolean => 4,
+        data_type => get_shader_data_type_size(data_type)
+    }
+}
+
+fn align_to(offset: i32, alignment: i32) -> i32 {
+    (offset + alignment - 1) / alignment * alignment
+}
+
 pub struct BufferLayout {
     elements: Vec<BufferElement>,
-    stride: i32
+    stride: i32,
+    divisor: u32
 }
 
 impl BufferLayout {
@@ -108,71 +164,193 @@ impl BufferLayout {
 
         BufferLayout {
             elements,
-            stride
+            stride,
+            divisor: 0
         }
     }
+
+    /// Advances the attributes once every `divisor` instances instead of once per vertex.
+    pub fn with_divisor(mut self, divisor: u32) -> BufferLayout {
+        self.divisor = divisor;
+        self
+    }
+
+    pub fn get_divisor(&self) -> u32 {
+        self.divisor
+    }
+
+    pub fn get_stride(&self) -> i32 {
+        self.stride
+    }
+
+    /// Lays the elements out following the std140 rules used by uniform blocks, stride
+    /// being the size of the whole block.
+    pub fn new_std140(mut elements: Vec<BufferElement>) -> BufferLayout {
+        let mut offset: i32 = 0;
+
+        for element in elements.iter_mut() {
+            element.size = get_std140_size(element.data_type);
+            element.offset = align_to(offset, get_std140_alignment(element.data_type));
+            offset = element.offset + element.size;
+        }
+
+        BufferLayout {
+            elements,
+            stride: align_to(offset, 16),
+            divisor: 0
+        }
+    }
+
+    fn get_element(&self, name: &str) -> Option<&BufferElement> {
+        self.elements.iter().find(|element| element.name == name)
+    }
 }
 
-pub struct VertexBuffer {
-    vertices: Vec<f32>,
+/// A plain old data struct that can be uploaded to a `VertexBuffer`. Usually derived, see
+/// `adze_derive::Vertex`.
+pub trait Vertex: Pod {
+    fn layout() -> BufferLayout;
+}
+
+/// A field type that maps to a single shader attribute.
+pub trait VertexAttribute {
+    const DATA_TYPE: ShaderDataType;
+}
+
+impl VertexAttribute for f32 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Float1;
+}
+
+impl VertexAttribute for Vec2 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Float2;
+}
+
+impl VertexAttribute for Vec3 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Float3;
+}
+
+impl VertexAttribute for Vec4 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Float4;
+}
+
+impl VertexAttribute for Mat3 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Matrix3;
+}
+
+impl VertexAttribute for Mat4 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Matrix4;
+}
+
+impl VertexAttribute for i32 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Integer1;
+}
+
+impl VertexAttribute for IVec2 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Integer2;
+}
+
+impl VertexAttribute for IVec3 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Integer3;
+}
+
+impl VertexAttribute for IVec4 {
+    const DATA_TYPE: ShaderDataType = ShaderDataType::Integer4;
+}
+
+pub struct VertexBuffer<T: Vertex> {
     layout: BufferLayout,
-    renderer_id: Buffer
+    capacity: usize,
+    renderer_id: Buffer,
+    vertex: PhantomData<T>
 }
 
-impl VertexBuffer {
-    pub fn new(gl: &glow::Context, vertices: Vec<f32>, layout: BufferLayout) -> VertexBuffer {
+impl<T: Vertex> VertexBuffer<T> {
+    fn create(gl: &glow::Context) -> (Buffer, BufferLayout) {
+        let layout = T::layout();
+        debug_assert_eq!(layout.stride as usize, mem::size_of::<T>(), "vertex layout doesn't match its struct");
+
         unsafe {
             let renderer_id = gl.create_buffer().unwrap();
+            resource::track(GlResource::Buffer(renderer_id));
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(renderer_id));
+            (renderer_id, layout)
+        }
+    }
 
-            let vertices_u8: &[u8] = core::slice::from_raw_parts(
-                vertices.as_ptr() as *const u8,
-                vertices.len() * core::mem::size_of::<f32>(),
-            );
-            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices_u8, glow::STATIC_DRAW);
-            VertexBuffer {
-                vertices,
-                layout,
-                renderer_id
-            }
+    /// Creates a buffer holding `vertices` that isn't expected to change.
+    pub fn new(gl: &glow::Context, vertices: &[T]) -> VertexBuffer<T> {
+        let (renderer_id, layout) = VertexBuffer::<T>::create(gl);
+        unsafe {
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, bytemuck::cast_slice(vertices), glow::STATIC_DRAW);
+        }
+
+        VertexBuffer {
+            layout,
+            capacity: vertices.len(),
+            renderer_id,
+            vertex: PhantomData
         }
     }
 
-    pub fn from_size(gl: &glow::Context, size: i32, layout: BufferLayout) -> VertexBuffer {
+    /// Creates an empty buffer with room for `capacity` vertices, to be filled every frame
+    /// with `set_vertices`.
+    pub fn with_capacity(gl: &glow::Context, capacity: usize) -> VertexBuffer<T> {
+        let (renderer_id, layout) = VertexBuffer::<T>::create(gl);
         unsafe {
-            // Create a Vertex Buffer Object and copy the vertex data to it
-            let renderer_id = gl.create_buffer().unwrap();
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(renderer_id));
             gl.buffer_data_size(
                 glow::ARRAY_BUFFER,
-                size,
+                (capacity * mem::size_of::<T>()) as i32,
                 glow::DYNAMIC_DRAW,
             );
-            VertexBuffer {
-                vertices: vec![],
-                layout,
-                renderer_id
-            }
+        }
+
+        VertexBuffer {
+            layout,
+            capacity,
+            renderer_id,
+            vertex: PhantomData
+        }
+    }
+
+    pub fn set_vertices(&mut self, gl: &glow::Context, vertices: &[T]) {
+        assert!(vertices.len() <= self.capacity, "{} vertices don't fit in a buffer of {}", vertices.len(), self.capacity);
+        unsafe {
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.renderer_id));
+            gl.buffer_sub_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                0,
+                bytemuck::cast_slice(vertices)
+            );
         }
     }
 
-    fn set_vertices(&mut self, gl: &glow::Context, vertices: &Vec<QuadVertex>) {
+    /// Replaces the contents of a buffer that is rewritten every frame. The old storage is
+    /// orphaned first so the driver can hand out fresh memory instead of waiting for draws still
+    /// reading the previous contents. Persistent mapping would avoid the reallocation but needs
+    /// gl 4.4, orphaning gets most of the benefit on 3.3.
+    pub fn stream_vertices(&mut self, gl: &glow::Context, vertices: &[T]) {
+        assert!(vertices.len() <= self.capacity, "{} vertices don't fit in a buffer of {}", vertices.len(), self.capacity);
         unsafe {
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.renderer_id));
-            let vertices_u8: &[u8] = core::slice::from_raw_parts(
-                vertices.as_ptr() as *const u8,
-                vertices.len() * core::mem::size_of::<QuadVertex>(),
+            gl.buffer_data_size(
+                glow::ARRAY_BUFFER,
+                (self.capacity * mem::size_of::<T>()) as i32,
+                glow::STREAM_DRAW,
             );
             gl.buffer_sub_data_u8_slice(
                 glow::ARRAY_BUFFER,
                 0,
-                vertices_u8
+                bytemuck::cast_slice(vertices)
             );
         }
     }
 
-    fn get_vertices(&mut self) -> &Vec<f32> {
-        &self.vertices
+    pub fn get_layout(&self) -> &BufferLayout {
+        &self.layout
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
     }
 
     pub fn bind(&self, gl: &glow::Context) {
@@ -188,6 +366,12 @@ impl VertexBuffer {
     }
 }
 
+impl<T: Vertex> Drop for VertexBuffer<T> {
+    fn drop(&mut self) {
+        resource::release(GlResource::Buffer(self.renderer_id));
+    }
+}
+
 pub struct IndexBuffer {
     indices: Vec<u32>,
     renderer_id: Buffer
@@ -198,12 +382,9 @@ impl IndexBuffer {
         unsafe {
             // Create a Vertex Buffer Object and copy the vertex data to it
             let renderer_id = gl.create_buffer().unwrap();
+            resource::track(GlResource::Buffer(renderer_id));
             gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(renderer_id));
-            let indices_u8: &[u8] = core::slice::from_raw_parts(
-                indices.as_ptr() as *const u8,
-                indices.len() * core::mem::size_of::<u32>(),
-            );
-            gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, indices_u8, glow::STATIC_DRAW);
+            gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, bytemuck::cast_slice(indices.as_slice()), glow::STATIC_DRAW);
             IndexBuffer {
                 indices,
                 renderer_id
@@ -228,42 +409,73 @@ impl IndexBuffer {
     }
 }
 
+impl Drop for IndexBuffer {
+    fn drop(&mut self) {
+        resource::release(GlResource::Buffer(self.renderer_id));
+    }
+}
+
 pub struct VertexArray {
-    vertex_buffer: buffer::VertexBuffer,
-    index_buffer: buffer::IndexBuffer,
+    index_buffer: IndexBuffer,
+    attribute_count: u32,
     renderer_id: glow::VertexArray
 }
 
 impl VertexArray {
-    pub fn new(gl: &glow::Context, index_buffer: buffer::IndexBuffer, vertex_buffer: buffer::VertexBuffer) -> VertexArray {
+    pub fn new(gl: &glow::Context, index_buffer: IndexBuffer) -> VertexArray {
         unsafe {
             // Create Vertex Array Object
             let renderer_id = gl.create_vertex_array().unwrap();
+            resource::track(GlResource::VertexArray(renderer_id));
             gl.bind_vertex_array(Some(renderer_id));
             index_buffer.bind(gl);
-            vertex_buffer.bind(gl);
-            for (index, element) in vertex_buffer.layout.elements.iter().enumerate() {
-                // Specify the layout of the vertex data
-                gl.enable_vertex_attrib_array(index as u32);
-                gl.vertex_attrib_pointer_f32(
-                    index as u32,
-                    element.get_component_count(),
-                    to_opengl_type(element.data_type),
-                    false,
-                    vertex_buffer.layout.stride,
-                    element.offset,
-                );
-            }
             VertexArray {
-                vertex_buffer,
                 index_buffer,
+                attribute_count: 0,
                 renderer_id
             }
         }
     }
 
-    pub fn set_vertices(&mut self, gl: &glow::Context, vertices: &Vec<QuadVertex>) {
-        self.vertex_buffer.set_vertices(gl, vertices);
+    /// Sets up the attributes of `vertex_buffer` after the ones of the buffers added before it,
+    /// so the first buffer starts at location 0.
+    pub fn add_vertex_buffer<T: Vertex>(&mut self, gl: &glow::Context, vertex_buffer: &VertexBuffer<T>) {
+        let layout = vertex_buffer.get_layout();
+        unsafe {
+            gl.bind_vertex_array(Some(self.renderer_id));
+            vertex_buffer.bind(gl);
+            for element in layout.elements.iter() {
+                let columns = element.get_column_count();
+                let components = element.get_component_count() / columns;
+
+                for column in 0..columns {
+                    // Specify the layout of the vertex data
+                    let index = self.attribute_count;
+                    let offset = element.offset + column * element.size / columns;
+                    gl.enable_vertex_attrib_array(index);
+                    if element.is_integer() {
+                        gl.vertex_attrib_pointer_i32(
+                            index,
+                            components,
+                            to_opengl_type(element.data_type),
+                            layout.stride,
+                            offset,
+                        );
+                    } else {
+                        gl.vertex_attrib_pointer_f32(
+                            index,
+                            components,
+                            to_opengl_type(element.data_type),
+                            element.normalized,
+                            layout.stride,
+                            offset,
+                        );
+                    }
+                    gl.vertex_attrib_divisor(index, layout.divisor);
+                    self.attribute_count += 1;
+                }
+            }
+        }
     }
 
     pub fn get_indices_len(&self) -> usize {
@@ -281,4 +493,163 @@ impl VertexArray {
             gl.bind_vertex_array(None);
         }
     }
-}
\ No newline at end of file
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        resource::release(GlResource::VertexArray(self.renderer_id));
+    }
+}
+
+pub struct UniformBuffer {
+    layout: BufferLayout,
+    data: Vec<u8>,
+    binding: u32,
+    renderer_id: Buffer
+}
+
+impl UniformBuffer {
+    pub fn new(gl: &glow::Context, layout: BufferLayout, binding: u32) -> UniformBuffer {
+        unsafe {
+            let renderer_id = gl.create_buffer().unwrap();
+            resource::track(GlResource::Buffer(renderer_id));
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(renderer_id));
+            gl.buffer_data_size(glow::UNIFORM_BUFFER, layout.stride, glow::DYNAMIC_DRAW);
+            gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+            UniformBuffer {
+                data: vec![0; layout.stride as usize],
+                layout,
+                binding,
+                renderer_id
+            }
+        }
+    }
+
+    fn write(&mut self, name: &str, bytes: &[u8]) {
+        let offset = match self.layout.get_element(name) {
+            Some(element) => element.offset as usize,
+            None => panic!("Uniform block has no member {}", name)
+        };
+
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn write_floats(&mut self, name: &str, values: &[f32]) {
+        let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_ne_bytes()).collect();
+        self.write(name, bytes.as_slice());
+    }
+
+    pub fn set_integer1(&mut self, name: &str, value: i32) {
+        self.write(name, &value.to_ne_bytes());
+    }
+
+    pub fn set_float1(&mut self, name: &str, value: f32) {
+        self.write_floats(name, &[value]);
+    }
+
+    pub fn set_float2(&mut self, name: &str, value: Vec2) {
+        self.write_floats(name, value.as_slice());
+    }
+
+    pub fn set_float3(&mut self, name: &str, value: Vec3) {
+        self.write_floats(name, value.as_slice());
+    }
+
+    pub fn set_float4(&mut self, name: &str, value: Vec4) {
+        self.write_floats(name, value.as_slice());
+    }
+
+    pub fn set_matrix3(&mut self, name: &str, matrix: &Mat3) {
+        let mut columns = [0.0; 12];
+        for (column, values) in matrix.as_slice().chunks(3).enumerate() {
+            columns[column * 4..column * 4 + 3].copy_from_slice(values);
+        }
+        self.write_floats(name, &columns);
+    }
+
+    pub fn set_matrix4(&mut self, name: &str, matrix: &Mat4) {
+        self.write_floats(name, matrix.as_slice());
+    }
+
+    /// Copies the block to the gpu and binds it to its binding point so every shader using
+    /// the block sees the new values.
+    pub fn upload(&self, gl: &glow::Context) {
+        unsafe {
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(self.renderer_id));
+            gl.buffer_sub_data_u8_slice(glow::UNIFORM_BUFFER, 0, self.data.as_slice());
+            gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+            gl.bind_buffer_base(glow::UNIFORM_BUFFER, self.binding, Some(self.renderer_id));
+        }
+    }
+
+    pub fn get_binding(&self) -> u32 {
+        self.binding
+    }
+}
+
+impl Drop for UniformBuffer {
+    fn drop(&mut self) {
+        resource::release(GlResource::Buffer(self.renderer_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use crate::renderer::QuadVertex;
+    use crate::renderer::buffer::{BufferElement, BufferLayout, ShaderDataType, Vertex};
+    use crate::renderer::mesh::MeshVertex;
+
+    #[test]
+    fn std140_layout_pads_vectors_and_matrices() {
+        let layout = BufferLayout::new_std140(
+            vec![
+                BufferElement::new("a".to_string(), ShaderDataType::Float1, false),
+                BufferElement::new("b".to_string(), ShaderDataType::Float3, false),
+                BufferElement::new("c".to_string(), ShaderDataType::Float1, false),
+                BufferElement::new("d".to_string(), ShaderDataType::Matrix3, false),
+                BufferElement::new("e".to_string(), ShaderDataType::Float2, false),
+            ]
+        );
+
+        let offsets: Vec<i32> = layout.elements.iter().map(|element| element.offset).collect();
+        assert_eq!(offsets, vec![0, 16, 28, 32, 80]);
+        assert_eq!(layout.stride, 96);
+    }
+
+    #[test]
+    fn packed_layout_sizes_matrices_by_column() {
+        let layout = BufferLayout::new(
+            vec![
+                BufferElement::new("transform".to_string(), ShaderDataType::Matrix3, false),
+                BufferElement::new("color".to_string(), ShaderDataType::Float4, false),
+            ]
+        );
+
+        assert_eq!(layout.elements[0].get_column_count(), 3);
+        assert_eq!(layout.elements[1].offset, 36);
+        assert_eq!(layout.stride, 52);
+    }
+
+    #[test]
+    fn derived_layouts_match_the_struct_fields() {
+        let layout = QuadVertex::layout();
+        let elements: Vec<(&str, i32, i32)> = layout.elements.iter()
+            .map(|element| (element.name.as_str(), element.offset, element.data_type as i32))
+            .collect();
+        assert_eq!(elements, vec![
+            ("position", mem::offset_of!(QuadVertex, position) as i32, ShaderDataType::Float3 as i32),
+            ("color", mem::offset_of!(QuadVertex, color) as i32, ShaderDataType::Float4 as i32),
+            ("texture_coordinate", mem::offset_of!(QuadVertex, texture_coordinate) as i32, ShaderDataType::Float2 as i32),
+            ("texture_index", mem::offset_of!(QuadVertex, texture_index) as i32, ShaderDataType::Float1 as i32)
+        ]);
+        assert_eq!(layout.stride, mem::size_of::<QuadVertex>() as i32);
+        assert_eq!(layout.divisor, 0);
+
+        let layout = MeshVertex::layout();
+        let offsets: Vec<i32> = layout.elements.iter().map(|element| element.offset).collect();
+        assert_eq!(offsets, vec![0, 12, 24]);
+        assert_eq!(layout.stride, mem::size_of::<MeshVertex>() as i32);
+    }
+}
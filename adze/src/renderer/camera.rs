@@ -1,12 +1,96 @@
 use nalgebra_glm as glm;
 use glm::{Mat4, Vec3, Vec2};
 use crate::app::App;
-use crate::glutin::event::VirtualKeyCode;
+use crate::glutin::event::{MouseButton, VirtualKeyCode};
+use crate::renderer::view;
+
+/// Anything the renderer can draw from, see `Renderer::begin`.
+pub trait Camera {
+    fn get_projection_view(&self) -> Mat4;
+}
+
+/// A rectangle of the window in pixels, with the origin at the top left like cursor positions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Viewport {
+        Viewport {
+            x,
+            y,
+            width,
+            height
+        }
+    }
+
+    /// A viewport covering a whole window or render target of the given size.
+    pub fn from_size(size: Vec2) -> Viewport {
+        Viewport::new(0.0, 0.0, size.x, size.y)
+    }
+
+    pub fn contains(&self, pixel: Vec2) -> bool {
+        pixel.x >= self.x && pixel.x < self.x + self.width && pixel.y >= self.y && pixel.y < self.y + self.height
+    }
+}
+
+/// An axis aligned rectangle in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: Vec2,
+    pub max: Vec2
+}
+
+impl Bounds {
+    pub fn new(min: Vec2, max: Vec2) -> Bounds {
+        Bounds {
+            min,
+            max
+        }
+    }
+
+    pub fn from_center(center: Vec2, size: Vec2) -> Bounds {
+        Bounds::new(center - size * 0.5, center + size * 0.5)
+    }
+
+    /// The smallest bounds containing every point, `None` without any.
+    pub fn from_points(points: &[Vec2]) -> Option<Bounds> {
+        let (first, rest) = points.split_first()?;
+        let mut bounds = Bounds::new(*first, *first);
+        for point in rest.iter() {
+            bounds.min = glm::min2(&bounds.min, point);
+            bounds.max = glm::max2(&bounds.max, point);
+        }
+        Some(bounds)
+    }
+
+    pub fn get_center(&self) -> Vec2 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn get_size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x && point.y >= self.min.y && point.y <= self.max.y
+    }
+
+    pub fn intersects(&self, other: &Bounds) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x && self.min.y <= other.max.y && self.max.y >= other.min.y
+    }
+}
 
 pub struct OrthographicCamera {
     projection: Mat4,
     view: Mat4,
     projection_view: Mat4,
+    inverse_projection_view: Mat4,
+    size: Vec2,
     position: Vec3,
     rotation: f32,
 }
@@ -18,6 +102,8 @@ impl OrthographicCamera {
             projection,
             view: glm::identity(),
             projection_view: projection * glm::identity(),
+            inverse_projection_view: glm::inverse(&projection),
+            size: glm::vec2(right - left, top - bottom),
             position: glm::vec3(0.0, 0.0,  0.0),
             rotation: 0.0,
         }
@@ -29,18 +115,63 @@ impl OrthographicCamera {
         let transform =  translation* glm::rotate(&glm::identity(), self.rotation,&glm::vec3(0.0, 0.0, 1.0));
         //nalgebra::try_invert_to(transform, &mut self.view);
         self.view = glm::inverse(&transform);
-        self.projection_view = self.projection * self.view;
+        self.update_projection_view();
     }
 
     pub fn set_projection(&mut self, left: f32, right: f32, bottom: f32, top: f32) {
         self.projection = Mat4::new_orthographic(left, right, bottom, top,-1.0, 1.0);
+        self.size = glm::vec2(right - left, top - bottom);
+        self.update_projection_view();
+    }
+
+    /// Width and height of the view in world units.
+    pub fn get_size(&self) -> Vec2 {
+        self.size
+    }
+
+    fn update_projection_view(&mut self) {
         self.projection_view = self.projection * self.view;
+        self.inverse_projection_view = glm::inverse(&self.projection_view);
     }
 
     pub fn get_projection_view(&self) -> Mat4 {
         self.projection_view
     }
 
+    /// The point in the world under a pixel of `viewport`, like the cursor position.
+    pub fn screen_to_world(&self, pixel: Vec2, viewport: &Viewport) -> Vec2 {
+        let ndc = glm::vec2(
+            (pixel.x - viewport.x) / viewport.width * 2.0 - 1.0,
+            1.0 - (pixel.y - viewport.y) / viewport.height * 2.0
+        );
+        self.ndc_to_world(ndc)
+    }
+
+    /// The pixel of `viewport` a point in the world is drawn at.
+    pub fn world_to_screen(&self, world: Vec2, viewport: &Viewport) -> Vec2 {
+        let clip = self.projection_view * glm::vec4(world.x, world.y, 0.0, 1.0);
+        glm::vec2(
+            viewport.x + (clip.x / clip.w + 1.0) * 0.5 * viewport.width,
+            viewport.y + (1.0 - clip.y / clip.w) * 0.5 * viewport.height
+        )
+    }
+
+    fn ndc_to_world(&self, ndc: Vec2) -> Vec2 {
+        let world = self.inverse_projection_view * glm::vec4(ndc.x, ndc.y, 0.0, 1.0);
+        glm::vec2(world.x / world.w, world.y / world.w)
+    }
+
+    /// The part of the world the camera sees, grown to stay axis aligned when it is rotated.
+    /// Anything outside of it can be culled.
+    pub fn get_visible_bounds(&self) -> Bounds {
+        Bounds::from_points(&[
+            self.ndc_to_world(glm::vec2(-1.0, -1.0)),
+            self.ndc_to_world(glm::vec2(1.0, -1.0)),
+            self.ndc_to_world(glm::vec2(1.0, 1.0)),
+            self.ndc_to_world(glm::vec2(-1.0, 1.0))
+        ]).unwrap()
+    }
+
     pub fn set_rotation(&mut self, value: f32) {
         self.rotation = value;
     }
@@ -58,14 +189,444 @@ impl OrthographicCamera {
     }
 }
 
+impl Camera for OrthographicCamera {
+    fn get_projection_view(&self) -> Mat4 {
+        self.projection_view
+    }
+}
+
+// keeps the camera from flipping over when looking straight up or down
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// A camera for 3D scenes. It looks down -z with a yaw and pitch of zero, yaw turns it to the
+/// right and pitch up.
+pub struct PerspectiveCamera {
+    projection: Mat4,
+    view: Mat4,
+    projection_view: Mat4,
+    inverse_projection_view: Mat4,
+    position: Vec3,
+    yaw: f32,
+    pitch: f32,
+    fov: f32,
+    aspect_ratio: f32,
+    near: f32,
+    far: f32
+}
+
+impl PerspectiveCamera {
+    /// `fov` is the vertical field of view in radians.
+    pub fn new(fov: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        let mut camera = PerspectiveCamera {
+            projection: glm::identity(),
+            view: glm::identity(),
+            projection_view: glm::identity(),
+            inverse_projection_view: glm::identity(),
+            position: glm::vec3(0.0, 0.0, 0.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            fov,
+            aspect_ratio,
+            near,
+            far
+        };
+        camera.set_projection(fov, aspect_ratio, near, far);
+        camera.recalculate_matrix();
+        camera
+    }
+
+    pub fn set_projection(&mut self, fov: f32, aspect_ratio: f32, near: f32, far: f32) {
+        self.fov = fov;
+        self.aspect_ratio = aspect_ratio;
+        self.near = near;
+        self.far = far;
+        self.projection = glm::perspective(aspect_ratio, fov, near, far);
+        self.update_projection_view();
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.set_projection(self.fov, aspect_ratio, self.near, self.far);
+    }
+
+    pub fn get_aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.set_projection(fov, self.aspect_ratio, self.near, self.far);
+    }
+
+    pub fn get_fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn recalculate_matrix(&mut self) {
+        self.view = glm::look_at(&self.position, &(self.position + self.get_forward()), &glm::vec3(0.0, 1.0, 0.0));
+        self.update_projection_view();
+    }
+
+    fn update_projection_view(&mut self) {
+        self.projection_view = self.projection * self.view;
+        self.inverse_projection_view = glm::inverse(&self.projection_view);
+    }
+
+    pub fn get_projection_view(&self) -> Mat4 {
+        self.projection_view
+    }
+
+    pub fn get_view(&self) -> Mat4 {
+        self.view
+    }
+
+    pub fn get_forward(&self) -> Vec3 {
+        glm::vec3(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            -self.yaw.cos() * self.pitch.cos()
+        )
+    }
+
+    pub fn get_right(&self) -> Vec3 {
+        glm::vec3(self.yaw.cos(), 0.0, self.yaw.sin())
+    }
+
+    pub fn get_up(&self) -> Vec3 {
+        glm::cross(&self.get_right(), &self.get_forward())
+    }
+
+    /// Turns the camera toward `target`.
+    pub fn look_at(&mut self, target: Vec3) {
+        let direction = target - self.position;
+        if direction == glm::vec3(0.0, 0.0, 0.0) {
+            return;
+        }
+        let direction = direction.normalize();
+        self.yaw = direction.x.atan2(-direction.z);
+        self.pitch = direction.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Where a ray through a pixel of `viewport` starts on the near plane and the direction it
+    /// goes, for picking things in the world with the cursor.
+    pub fn screen_to_ray(&self, pixel: Vec2, viewport: &Viewport) -> (Vec3, Vec3) {
+        let ndc = glm::vec2(
+            (pixel.x - viewport.x) / viewport.width * 2.0 - 1.0,
+            1.0 - (pixel.y - viewport.y) / viewport.height * 2.0
+        );
+        let unproject = |z: f32| {
+            let world = self.inverse_projection_view * glm::vec4(ndc.x, ndc.y, z, 1.0);
+            glm::vec3(world.x, world.y, world.z) / world.w
+        };
+        let near = unproject(-1.0);
+        (near, (unproject(1.0) - near).normalize())
+    }
+
+    /// The pixel of `viewport` a point in the world is drawn at, `None` when it is behind the
+    /// camera.
+    pub fn world_to_screen(&self, world: Vec3, viewport: &Viewport) -> Option<Vec2> {
+        let clip = self.projection_view * glm::vec4(world.x, world.y, world.z, 1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        Some(glm::vec2(
+            viewport.x + (clip.x / clip.w + 1.0) * 0.5 * viewport.width,
+            viewport.y + (1.0 - clip.y / clip.w) * 0.5 * viewport.height
+        ))
+    }
+
+    pub fn set_position(&mut self, value: Vec3) {
+        self.position = value;
+    }
+
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn set_yaw(&mut self, value: f32) {
+        self.yaw = value;
+    }
+
+    pub fn get_yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn set_pitch(&mut self, value: f32) {
+        self.pitch = value.clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn get_pitch(&self) -> f32 {
+        self.pitch
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_projection_view(&self) -> Mat4 {
+        self.projection_view
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EditorDrag {
+    None,
+    Orbit,
+    Pan,
+    Fly
+}
+
+/// Moves a `PerspectiveCamera` the way 3D editors do. Dragging with the left button orbits
+/// around a focus point, the middle button pans and the wheel moves closer or further away.
+/// Holding the right button looks around in place and flies with W, A, S and D, Q and E
+/// moving down and up.
+pub struct EditorCameraController {
+    camera: PerspectiveCamera,
+    focus: Vec3,
+    distance: f32,
+    min_distance: f32,
+    max_distance: f32,
+    zoom_step: f32,
+    // radians per pixel dragged
+    look_speed: f32,
+    fly_speed: f32,
+    viewport: Viewport,
+    drag: EditorDrag,
+    mouse_position: Option<Vec2>
+}
+
+impl EditorCameraController {
+    pub fn new(aspect_ratio: f32) -> Self {
+        let mut controller = EditorCameraController {
+            camera: PerspectiveCamera::new(45_f32.to_radians(), aspect_ratio, 0.1, 1000.0),
+            focus: glm::vec3(0.0, 0.0, 0.0),
+            distance: 5.0,
+            min_distance: 0.1,
+            max_distance: 500.0,
+            zoom_step: 1.1,
+            look_speed: 0.005,
+            fly_speed: 3.0,
+            viewport: Viewport::new(0.0, 0.0, aspect_ratio, 1.0),
+            drag: EditorDrag::None,
+            mouse_position: None
+        };
+        controller.camera.set_pitch(-0.4);
+        controller.update_camera();
+        controller
+    }
+
+    pub fn get_camera(&mut self) -> &mut PerspectiveCamera { &mut self.camera }
+
+    /// The point the camera orbits around and looks at.
+    pub fn set_focus(&mut self, value: Vec3) {
+        self.focus = value;
+        self.update_camera();
+    }
+
+    pub fn get_focus(&self) -> Vec3 {
+        self.focus
+    }
+
+    pub fn set_distance(&mut self, value: f32) {
+        self.distance = value.clamp(self.min_distance, self.max_distance);
+        self.update_camera();
+    }
+
+    pub fn get_distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn set_distance_limits(&mut self, min_distance: f32, max_distance: f32) {
+        self.min_distance = min_distance;
+        self.max_distance = max_distance;
+        self.set_distance(self.distance);
+    }
+
+    /// How much one wheel step multiplies the distance by.
+    pub fn set_zoom_step(&mut self, value: f32) {
+        self.zoom_step = value;
+    }
+
+    /// Radians turned per pixel the mouse is dragged.
+    pub fn set_look_speed(&mut self, value: f32) {
+        self.look_speed = value;
+    }
+
+    pub fn get_look_speed(&self) -> f32 {
+        self.look_speed
+    }
+
+    /// Units per second.
+    pub fn set_fly_speed(&mut self, value: f32) {
+        self.fly_speed = value;
+    }
+
+    pub fn get_fly_speed(&self) -> f32 {
+        self.fly_speed
+    }
+
+    fn update_camera(&mut self) {
+        let position = self.focus - self.camera.get_forward() * self.distance;
+        self.camera.set_position(position);
+        self.camera.recalculate_matrix();
+    }
+
+    fn rotate(&mut self, delta: Vec2) {
+        self.camera.set_yaw(self.camera.get_yaw() + delta.x * self.look_speed);
+        self.camera.set_pitch(self.camera.get_pitch() - delta.y * self.look_speed);
+    }
+
+    fn apply_drag(&mut self, delta: Vec2) {
+        match self.drag {
+            EditorDrag::Orbit => {
+                self.rotate(delta);
+                self.update_camera();
+            }
+            EditorDrag::Fly => {
+                // turns in place, so the focus moves instead of the camera
+                let position = self.camera.get_position();
+                self.rotate(delta);
+                self.focus = position + self.camera.get_forward() * self.distance;
+                self.update_camera();
+            }
+            EditorDrag::Pan => {
+                // moves the focus so it stays under the cursor
+                let units_per_pixel = 2.0 * self.distance * (self.camera.get_fov() * 0.5).tan() / self.viewport.height;
+                self.focus += (self.camera.get_up() * delta.y - self.camera.get_right() * delta.x) * units_per_pixel;
+                self.update_camera();
+            }
+            EditorDrag::None => {}
+        }
+    }
+
+    pub fn on_window_resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.viewport = Viewport::new(0.0, 0.0, width as f32, height as f32);
+        self.camera.set_aspect_ratio(width as f32 / height as f32);
+    }
+
+    fn get_drag(button: MouseButton) -> EditorDrag {
+        match button {
+            MouseButton::Left => EditorDrag::Orbit,
+            MouseButton::Middle => EditorDrag::Pan,
+            MouseButton::Right => EditorDrag::Fly,
+            MouseButton::Other(_) => EditorDrag::None
+        }
+    }
+
+    /// Starts the drag of `button`, other buttons are ignored until it is released.
+    pub fn on_mouse_press(&mut self, button: MouseButton) -> bool {
+        let drag = EditorCameraController::get_drag(button);
+        if drag == EditorDrag::None || self.drag != EditorDrag::None {
+            return false;
+        }
+        self.drag = drag;
+        true
+    }
+
+    pub fn on_mouse_release(&mut self, button: MouseButton) -> bool {
+        if self.drag == EditorDrag::None || EditorCameraController::get_drag(button) != self.drag {
+            return false;
+        }
+        self.drag = EditorDrag::None;
+        true
+    }
+
+    pub fn on_mouse_move(&mut self, position: Vec2) -> bool {
+        let last = self.mouse_position.replace(position);
+        match last {
+            Some(last) if self.drag != EditorDrag::None => {
+                self.apply_drag(position - last);
+                true
+            }
+            _ => false
+        }
+    }
+
+    pub fn on_mouse_scroll(&mut self, delta: Vec2) -> bool {
+        self.set_distance(self.distance * self.zoom_step.powf(-delta.y));
+        true
+    }
+
+    pub fn on_tick(&mut self, dt: f32) {
+        if self.drag != EditorDrag::Fly {
+            return;
+        }
+
+        let mut direction = glm::vec3(0.0, 0.0, 0.0);
+        if App::is_key_pressed(VirtualKeyCode::W) {
+            direction += self.camera.get_forward();
+        }
+        if App::is_key_pressed(VirtualKeyCode::S) {
+            direction -= self.camera.get_forward();
+        }
+        if App::is_key_pressed(VirtualKeyCode::D) {
+            direction += self.camera.get_right();
+        }
+        if App::is_key_pressed(VirtualKeyCode::A) {
+            direction -= self.camera.get_right();
+        }
+        if App::is_key_pressed(VirtualKeyCode::E) {
+            direction.y += 1.0;
+        }
+        if App::is_key_pressed(VirtualKeyCode::Q) {
+            direction.y -= 1.0;
+        }
+
+        if direction != glm::vec3(0.0, 0.0, 0.0) {
+            self.focus += direction.normalize() * self.fly_speed * dt;
+            self.update_camera();
+        }
+    }
+}
+
+/// The keys a `WasdCameraController` listens to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraKeyBindings {
+    pub up: VirtualKeyCode,
+    pub down: VirtualKeyCode,
+    pub left: VirtualKeyCode,
+    pub right: VirtualKeyCode,
+    pub rotate_left: VirtualKeyCode,
+    pub rotate_right: VirtualKeyCode
+}
+
+impl Default for CameraKeyBindings {
+    fn default() -> Self {
+        CameraKeyBindings {
+            up: VirtualKeyCode::W,
+            down: VirtualKeyCode::S,
+            left: VirtualKeyCode::A,
+            right: VirtualKeyCode::D,
+            rotate_left: VirtualKeyCode::Q,
+            rotate_right: VirtualKeyCode::E
+        }
+    }
+}
+
+/// Moves an `OrthographicCamera` with the keyboard and zooms it with the mouse wheel.
+///
+/// Movement follows the camera's rotation and is scaled by the zoom level, so it covers the
+/// same part of the screen at any zoom. Zooming multiplies the zoom level per wheel step and
+/// keeps the point under the cursor in place.
 pub struct WasdCameraController {
     camera: OrthographicCamera,
     position: Vec3,
     rotation: f32,
     zoom: f32,
+    target_zoom: f32,
+    min_zoom: f32,
+    max_zoom: f32,
+    zoom_step: f32,
+    zoom_smoothing: f32,
+    // the pixel zooming is centered on
+    zoom_anchor: Vec2,
     aspect_ratio: f32,
+    viewport: Viewport,
+    window_size: Vec2,
+    virtual_resolution: Option<Vec2>,
     translation_speed: f32,
     rotation_speed: f32,
+    rotation_enabled: bool,
+    bindings: CameraKeyBindings
 }
 
 impl WasdCameraController {
@@ -75,38 +636,94 @@ impl WasdCameraController {
                 -aspect_ratio, aspect_ratio, -1.0, 1.0,
             ),
             aspect_ratio,
+            viewport: Viewport::new(0.0, 0.0, aspect_ratio, 1.0),
+            window_size: glm::vec2(aspect_ratio, 1.0),
+            virtual_resolution: None,
             position: glm::vec3(0.0, 0.0, 0.0),
             rotation: 0.0,
             zoom: 1.0,
+            target_zoom: 1.0,
+            min_zoom: 0.25,
+            max_zoom: 100.0,
+            zoom_step: 1.1,
+            zoom_smoothing: 15.0,
+            zoom_anchor: glm::vec2(0.0, 0.0),
             translation_speed: 1.0,
             rotation_speed: 1.0,
+            rotation_enabled: true,
+            bindings: CameraKeyBindings::default()
         }
     }
 
     pub fn set_rotation(&mut self, value: f32) {
         self.rotation = value;
-        self.camera.set_rotation(value);
+        self.update_camera();
     }
 
     pub fn get_rotation(&self) -> f32 { self.rotation }
 
     pub fn get_camera(&mut self) -> &mut OrthographicCamera { &mut self.camera }
 
+    /// What the camera currently shows, for culling.
+    pub fn get_bounds(&self) -> Bounds {
+        self.camera.get_visible_bounds()
+    }
+
     pub fn set_position(&mut self, value: Vec3) {
         self.position = value;
-        self.camera.set_position(value);
+        self.update_camera();
     }
 
     pub fn get_position(&self) -> Vec3 {
         self.position
     }
 
+    /// Half the height of the view in world units, set without smoothing.
+    pub fn set_zoom(&mut self, value: f32) {
+        self.zoom = value.clamp(self.min_zoom, self.max_zoom);
+        self.target_zoom = self.zoom;
+        self.update_camera();
+    }
+
+    pub fn get_zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Panics when `min_zoom` is above `max_zoom` or either is NaN.
+    pub fn set_zoom_limits(&mut self, min_zoom: f32, max_zoom: f32) {
+        assert!(min_zoom <= max_zoom, "the smallest zoom {} is above the largest zoom {}", min_zoom, max_zoom);
+        self.min_zoom = min_zoom;
+        self.max_zoom = max_zoom;
+        self.set_zoom(self.zoom);
+    }
+
+    /// How much one wheel step multiplies the zoom by.
+    pub fn set_zoom_step(&mut self, value: f32) {
+        self.zoom_step = value;
+    }
+
+    /// How quickly the zoom catches up with the wheel, per second. Zero zooms instantly.
+    pub fn set_zoom_smoothing(&mut self, value: f32) {
+        self.zoom_smoothing = value;
+    }
+
+    /// Radians per second.
     pub fn set_rotation_speed(&mut self, value: f32) {
         self.rotation_speed = value;
     }
 
     pub fn get_rotation_speed(&self) -> f32 { self.rotation_speed }
 
+    /// Whether the rotation keys turn the camera.
+    pub fn set_rotation_enabled(&mut self, value: bool) {
+        self.rotation_enabled = value;
+    }
+
+    pub fn get_rotation_enabled(&self) -> bool {
+        self.rotation_enabled
+    }
+
+    /// View heights per second at a zoom of one.
     pub fn set_translation_speed(&mut self, value: f32) {
         self.translation_speed = value;
     }
@@ -115,36 +732,224 @@ impl WasdCameraController {
         self.translation_speed
     }
 
-    fn reset_projection(&mut self) {
+    /// Keeps the view at the aspect ratio of a virtual resolution set with
+    /// `Renderer::set_virtual_resolution`, whatever the window size. `None` follows the
+    /// window again.
+    pub fn set_virtual_resolution(&mut self, virtual_resolution: Option<(u32, u32)>) {
+        self.virtual_resolution = virtual_resolution.map(|(width, height)| glm::vec2(width as f32, height as f32));
+        self.fit_to_window();
+    }
+
+    pub fn set_bindings(&mut self, bindings: CameraKeyBindings) {
+        self.bindings = bindings;
+    }
+
+    pub fn get_bindings(&self) -> CameraKeyBindings {
+        self.bindings
+    }
+
+    fn update_camera(&mut self) {
         self.camera.set_projection(
             -self.aspect_ratio * self.zoom,
             self.aspect_ratio * self.zoom,
             -self.zoom,
             self.zoom
         );
+        self.camera.set_position(self.position);
+        self.camera.set_rotation(self.rotation);
+        self.camera.recalculate_matrix();
+    }
+
+    /// Changes the zoom while keeping the point under the zoom anchor where it is.
+    fn zoom_to(&mut self, zoom: f32) {
+        let before = self.camera.screen_to_world(self.zoom_anchor, &self.viewport);
+        self.zoom = zoom;
+        self.update_camera();
+        let after = self.camera.screen_to_world(self.zoom_anchor, &self.viewport);
+        self.position += glm::vec3(before.x - after.x, before.y - after.y, 0.0);
+        self.update_camera();
     }
 
     pub fn on_window_resize(&mut self, width: u32, height: u32) {
-        self.aspect_ratio = width as f32 / height as f32;
-        self.reset_projection();
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.window_size = glm::vec2(width as f32, height as f32);
+        self.fit_to_window();
+    }
+
+    fn fit_to_window(&mut self) {
+        // with a virtual resolution the window only changes where the view is shown, which
+        // the cursor needs for zooming
+        self.viewport = match self.virtual_resolution {
+            Some(virtual_resolution) => view::letterbox(self.window_size, virtual_resolution),
+            None => Viewport::from_size(self.window_size)
+        };
+        self.aspect_ratio = self.viewport.width / self.viewport.height;
+        self.update_camera();
     }
 
     pub fn on_mouse_scroll(&mut self, delta: Vec2) -> bool {
-        self.zoom = self.zoom - *delta.get(1).unwrap() as f32;
-        self.zoom = self.zoom.max(0.25);
-        self.reset_projection();
-        false
+        self.zoom_at(App::get_mouse_position(), delta.y)
     }
 
-    pub fn on_tick(&mut self) {
-        if App::is_key_pressed(VirtualKeyCode::A) {
-            self.camera.set_position(self.camera.get_position() + glm::vec3(-0.01, 0.0, 0.0));
-        } else if App::is_key_pressed(VirtualKeyCode::D) {
-            self.camera.set_position(self.camera.get_position() + glm::vec3(0.01, 0.0, 0.0));
-        } else if App::is_key_pressed(VirtualKeyCode::W) {
-            self.camera.set_position(self.camera.get_position() + glm::vec3(0.0, 0.01, 0.0));
-        } else if App::is_key_pressed(VirtualKeyCode::S) {
-            self.camera.set_position(self.camera.get_position() + glm::vec3(0.0, -0.01, 0.0));
+    /// Zooms by `steps` wheel steps toward `pixel`, positive steps zoom in.
+    fn zoom_at(&mut self, pixel: Vec2, steps: f32) -> bool {
+        self.target_zoom = (self.target_zoom * self.zoom_step.powf(-steps)).clamp(self.min_zoom, self.max_zoom);
+        self.zoom_anchor = pixel;
+        if self.zoom_smoothing <= 0.0 {
+            self.zoom_to(self.target_zoom);
         }
+        true
     }
-}
\ No newline at end of file
+
+    pub fn on_tick(&mut self, dt: f32) {
+        let mut direction = glm::vec2(0.0, 0.0);
+        if App::is_key_pressed(self.bindings.up) {
+            direction.y += 1.0;
+        }
+        if App::is_key_pressed(self.bindings.down) {
+            direction.y -= 1.0;
+        }
+        if App::is_key_pressed(self.bindings.left) {
+            direction.x -= 1.0;
+        }
+        if App::is_key_pressed(self.bindings.right) {
+            direction.x += 1.0;
+        }
+
+        if direction != glm::vec2(0.0, 0.0) {
+            // move along the screen's axes, not the world's
+            let direction = glm::rotate_vec2(&direction.normalize(), self.rotation);
+            let distance = self.translation_speed * self.zoom * dt;
+            self.position += glm::vec3(direction.x, direction.y, 0.0) * distance;
+        }
+
+        if self.rotation_enabled {
+            if App::is_key_pressed(self.bindings.rotate_left) {
+                self.rotation += self.rotation_speed * dt;
+            }
+            if App::is_key_pressed(self.bindings.rotate_right) {
+                self.rotation -= self.rotation_speed * dt;
+            }
+        }
+
+        self.update_camera();
+
+        if self.zoom != self.target_zoom {
+            let zoom = self.zoom + (self.target_zoom - self.zoom) * (1.0 - (-self.zoom_smoothing * dt).exp());
+            // snap once the difference can't be seen
+            let zoom = if (zoom - self.target_zoom).abs() < self.target_zoom * 1e-3 { self.target_zoom } else { zoom };
+            self.zoom_to(zoom);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glm;
+    use crate::glutin::event::MouseButton;
+    use crate::renderer::camera::{Bounds, EditorCameraController, OrthographicCamera, Viewport, WasdCameraController};
+
+    #[test]
+    fn screen_and_world_conversions_round_trip() {
+        let mut camera = OrthographicCamera::new(-2.0, 2.0, -1.0, 1.0);
+        camera.set_position(glm::vec3(1.0, 0.0, 0.0));
+        camera.recalculate_matrix();
+        let viewport = Viewport::new(0.0, 0.0, 400.0, 200.0);
+
+        // the top left pixel is the top left of the view
+        assert!(glm::distance(&camera.screen_to_world(glm::vec2(0.0, 0.0), &viewport), &glm::vec2(-1.0, 1.0)) < 1e-5);
+        assert!(glm::distance(&camera.world_to_screen(glm::vec2(1.0, 0.0), &viewport), &glm::vec2(200.0, 100.0)) < 1e-3);
+
+        let bounds = camera.get_visible_bounds();
+        assert!(glm::distance(&bounds.min, &glm::vec2(-1.0, -1.0)) < 1e-5);
+        assert!(glm::distance(&bounds.max, &glm::vec2(3.0, 1.0)) < 1e-5);
+        assert!(Bounds::from_points(&[]).is_none());
+
+        camera.set_rotation(std::f32::consts::FRAC_PI_2);
+        camera.recalculate_matrix();
+        let world = camera.screen_to_world(glm::vec2(37.0, 150.0), &viewport);
+        assert!(glm::distance(&camera.world_to_screen(world, &viewport), &glm::vec2(37.0, 150.0)) < 1e-3);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let mut controller = WasdCameraController::new(2.0);
+        controller.on_window_resize(400, 200);
+        controller.set_zoom_smoothing(0.0);
+
+        let cursor = glm::vec2(300.0, 50.0);
+        let before = controller.get_camera().screen_to_world(cursor, &Viewport::new(0.0, 0.0, 400.0, 200.0));
+        controller.zoom_at(cursor, 3.0);
+        let after = controller.get_camera().screen_to_world(cursor, &Viewport::new(0.0, 0.0, 400.0, 200.0));
+
+        assert!((controller.get_zoom() - 1.1_f32.powf(-3.0)).abs() < 1e-5);
+        assert!(glm::distance(&before, &after) < 1e-4);
+    }
+
+    #[test]
+    #[should_panic(expected = "the smallest zoom 4 is above the largest zoom 0.5")]
+    fn zoom_limits_cannot_be_swapped() {
+        let mut controller = WasdCameraController::new(2.0);
+        controller.set_zoom_limits(4.0, 0.5);
+    }
+
+    #[test]
+    fn virtual_resolutions_keep_their_aspect_ratio() {
+        let mut controller = WasdCameraController::new(2.0);
+        controller.set_virtual_resolution(Some((320, 180)));
+        controller.on_window_resize(1000, 1000);
+
+        let size = controller.get_camera().get_size();
+        assert!((size.x / size.y - 320.0 / 180.0).abs() < 1e-5);
+        // the cursor is converted within the letterboxed screen
+        let center = controller.get_camera().screen_to_world(glm::vec2(500.0, 500.0), &Viewport::new(20.0, 230.0, 960.0, 540.0));
+        assert!(glm::length(&center) < 1e-5);
+
+        controller.set_virtual_resolution(None);
+        let size = controller.get_camera().get_size();
+        assert!((size.x / size.y - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orbiting_keeps_the_focus_in_the_middle_of_the_view() {
+        let mut controller = EditorCameraController::new(2.0);
+        controller.on_window_resize(400, 200);
+        controller.set_focus(glm::vec3(1.0, 2.0, 3.0));
+        let viewport = Viewport::new(0.0, 0.0, 400.0, 200.0);
+
+        controller.on_mouse_move(glm::vec2(0.0, 0.0));
+        controller.on_mouse_press(MouseButton::Left);
+        controller.on_mouse_move(glm::vec2(120.0, -40.0));
+        controller.on_mouse_release(MouseButton::Left);
+
+        let camera = controller.get_camera();
+        assert!((camera.get_yaw() - 0.6).abs() < 1e-5);
+        assert!((glm::distance(&camera.get_position(), &glm::vec3(1.0, 2.0, 3.0)) - 5.0).abs() < 1e-4);
+        let center = camera.world_to_screen(glm::vec3(1.0, 2.0, 3.0), &viewport).unwrap();
+        assert!(glm::distance(&center, &glm::vec2(200.0, 100.0)) < 1e-2);
+
+        // a ray through the middle of the screen goes through the focus
+        let (origin, direction) = camera.screen_to_ray(glm::vec2(200.0, 100.0), &viewport);
+        let to_focus = glm::vec3(1.0, 2.0, 3.0) - origin;
+        assert!(glm::length(&glm::cross(&direction, &to_focus)) < 1e-3);
+    }
+
+    #[test]
+    fn other_buttons_do_not_interrupt_a_drag() {
+        let mut controller = EditorCameraController::new(2.0);
+        controller.on_window_resize(400, 200);
+        controller.on_mouse_move(glm::vec2(0.0, 0.0));
+        controller.on_mouse_press(MouseButton::Middle);
+        assert!(!controller.on_mouse_press(MouseButton::Right));
+        assert!(!controller.on_mouse_release(MouseButton::Right));
+
+        // still panning, without turning the camera
+        let yaw = controller.get_camera().get_yaw();
+        controller.on_mouse_move(glm::vec2(50.0, 0.0));
+        assert!(glm::length(&controller.get_focus()) > 0.1);
+        assert_eq!(controller.get_camera().get_yaw(), yaw);
+        assert!(controller.on_mouse_release(MouseButton::Middle));
+    }
+}
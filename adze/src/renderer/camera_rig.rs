@@ -0,0 +1,344 @@
+use nalgebra_glm as glm;
+use glm::{Vec2, Vec3};
+
+use crate::renderer::camera::{Bounds, OrthographicCamera, Viewport};
+
+/// What camera behaviors read and write every update.
+///
+/// `position` and `rotation` are where the camera rests and carry over between updates,
+/// `offset` and `rotation_offset` are added on top for a single update, so effects like shake
+/// don't move the camera for good. `goal` is likewise reset to `target` every update.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraView {
+    pub position: Vec2,
+    pub rotation: f32,
+    pub offset: Vec2,
+    pub rotation_offset: f32,
+    /// What the camera should look at and how fast it is moving, set with
+    /// `CameraRig::set_target`.
+    pub target: Vec2,
+    pub target_velocity: Vec2,
+    /// Where the camera heads this update, `DeadZone` moves it and `Follow` goes to it.
+    pub goal: Vec2,
+    /// How hard the camera is shaking, from zero to one.
+    pub trauma: f32,
+    /// Half the size of the view in world units.
+    pub half_size: Vec2,
+    pub pixels_per_unit: f32
+}
+
+/// One piece of camera behavior, rigs run them in the order they were added.
+pub trait CameraBehavior {
+    fn update(&mut self, view: &mut CameraView, dt: f32);
+}
+
+/// Only lets the target pull the camera once it leaves a rectangle around the camera's
+/// position. Goes before `Follow`, which then moves the camera toward the rectangle's edge.
+pub struct DeadZone {
+    size: Vec2
+}
+
+impl DeadZone {
+    pub fn new(size: Vec2) -> DeadZone {
+        DeadZone {
+            size
+        }
+    }
+}
+
+impl CameraBehavior for DeadZone {
+    fn update(&mut self, view: &mut CameraView, _dt: f32) {
+        let half = self.size * 0.5;
+        let inside = glm::clamp_vec(&view.goal, &(view.position - half), &(view.position + half));
+        view.goal = view.position + (view.goal - inside);
+    }
+}
+
+/// Moves the camera toward the goal. `damping` is how quickly it catches up per second,
+/// zero snaps to the target. `look_ahead` leads a moving target by that many seconds of its
+/// velocity, so more of the level in front of the player is on screen.
+pub struct Follow {
+    damping: f32,
+    look_ahead: f32
+}
+
+impl Follow {
+    pub fn new(damping: f32) -> Follow {
+        Follow {
+            damping,
+            look_ahead: 0.0
+        }
+    }
+
+    pub fn with_look_ahead(mut self, look_ahead: f32) -> Follow {
+        self.look_ahead = look_ahead;
+        self
+    }
+}
+
+impl CameraBehavior for Follow {
+    fn update(&mut self, view: &mut CameraView, dt: f32) {
+        let goal = view.goal + view.target_velocity * self.look_ahead;
+        if self.damping <= 0.0 {
+            view.position = goal;
+        } else {
+            // frame rate independent exponential smoothing
+            view.position += (goal - view.position) * (1.0 - (-self.damping * dt).exp());
+        }
+    }
+}
+
+/// Keeps the view inside the level. A level smaller than the view is centered instead.
+pub struct ClampToBounds {
+    bounds: Bounds
+}
+
+impl ClampToBounds {
+    pub fn new(bounds: Bounds) -> ClampToBounds {
+        ClampToBounds {
+            bounds
+        }
+    }
+
+    pub fn set_bounds(&mut self, bounds: Bounds) {
+        self.bounds = bounds;
+    }
+}
+
+impl CameraBehavior for ClampToBounds {
+    fn update(&mut self, view: &mut CameraView, _dt: f32) {
+        let center = self.bounds.get_center();
+        let clamp = |position: f32, min: f32, max: f32, half: f32, center: f32| {
+            if max - min < half * 2.0 {
+                center
+            } else {
+                position.clamp(min + half, max - half)
+            }
+        };
+        view.position = glm::vec2(
+            clamp(view.position.x, self.bounds.min.x, self.bounds.max.x, view.half_size.x, center.x),
+            clamp(view.position.y, self.bounds.min.y, self.bounds.max.y, view.half_size.y, center.y)
+        );
+    }
+}
+
+fn hash(x: i32, seed: u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x27d4_eb2d) ^ seed.wrapping_mul(0x1656_67b1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    (h & 0xffff) as f32 / 32767.5 - 1.0
+}
+
+/// Smooth noise between -1 and 1, so the shake wobbles instead of jumping every frame.
+fn noise(t: f32, seed: u32) -> f32 {
+    let i = t.floor();
+    let f = t - i;
+    let f = f * f * (3.0 - 2.0 * f);
+    let a = hash(i as i32, seed);
+    let b = hash(i as i32 + 1, seed);
+    a + (b - a) * f
+}
+
+/// Trauma based screen shake. Add trauma with `CameraRig::add_trauma`, the shake grows with
+/// the square of the trauma so small hits stay subtle, and trauma wears off over time.
+pub struct Shake {
+    max_offset: Vec2,
+    max_angle: f32,
+    frequency: f32,
+    recovery: f32,
+    time: f32
+}
+
+impl Shake {
+    pub fn new(max_offset: Vec2, max_angle: f32) -> Shake {
+        Shake {
+            max_offset,
+            max_angle,
+            frequency: 15.0,
+            recovery: 1.0,
+            time: 0.0
+        }
+    }
+
+    /// Noise samples per second.
+    pub fn with_frequency(mut self, frequency: f32) -> Shake {
+        self.frequency = frequency;
+        self
+    }
+
+    /// Trauma removed per second.
+    pub fn with_recovery(mut self, recovery: f32) -> Shake {
+        self.recovery = recovery;
+        self
+    }
+}
+
+impl CameraBehavior for Shake {
+    fn update(&mut self, view: &mut CameraView, dt: f32) {
+        self.time += dt;
+        let shake = view.trauma * view.trauma;
+        let t = self.time * self.frequency;
+        view.offset += glm::vec2(self.max_offset.x * noise(t, 1), self.max_offset.y * noise(t, 2)) * shake;
+        view.rotation_offset += self.max_angle * noise(t, 3) * shake;
+        view.trauma = (view.trauma - self.recovery * dt).max(0.0);
+    }
+}
+
+/// Rounds where the camera ends up to whole pixels so pixel art doesn't shimmer. Goes last.
+pub struct PixelSnap;
+
+impl CameraBehavior for PixelSnap {
+    fn update(&mut self, view: &mut CameraView, _dt: f32) {
+        if view.pixels_per_unit <= 0.0 {
+            return;
+        }
+        let position = view.position + view.offset;
+        let snapped = glm::round(&(position * view.pixels_per_unit)) / view.pixels_per_unit;
+        view.offset += snapped - position;
+    }
+}
+
+/// Drives an `OrthographicCamera` with a stack of behaviors.
+pub struct CameraRig {
+    view: CameraView,
+    behaviors: Vec<Box<dyn CameraBehavior>>
+}
+
+impl CameraRig {
+    pub fn new(position: Vec2) -> CameraRig {
+        CameraRig {
+            view: CameraView {
+                position,
+                rotation: 0.0,
+                offset: glm::vec2(0.0, 0.0),
+                rotation_offset: 0.0,
+                target: position,
+                target_velocity: glm::vec2(0.0, 0.0),
+                goal: position,
+                trauma: 0.0,
+                half_size: glm::vec2(1.0, 1.0),
+                pixels_per_unit: 0.0
+            },
+            behaviors: vec![]
+        }
+    }
+
+    pub fn with<B: CameraBehavior + 'static>(mut self, behavior: B) -> CameraRig {
+        self.push(behavior);
+        self
+    }
+
+    pub fn push<B: CameraBehavior + 'static>(&mut self, behavior: B) {
+        self.behaviors.push(Box::new(behavior));
+    }
+
+    pub fn set_target(&mut self, target: Vec2, velocity: Vec2) {
+        self.view.target = target;
+        self.view.target_velocity = velocity;
+    }
+
+    /// Adds to the shake, trauma is capped at one.
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.view.trauma = (self.view.trauma + trauma).min(1.0);
+    }
+
+    pub fn set_position(&mut self, position: Vec2) {
+        self.view.position = position;
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.view.rotation = rotation;
+    }
+
+    pub fn get_view(&self) -> &CameraView {
+        &self.view
+    }
+
+    /// Runs the behaviors and moves the camera. `viewport` is what the camera is drawn to,
+    /// used to snap to its pixels.
+    pub fn update(&mut self, camera: &mut OrthographicCamera, viewport: &Viewport, dt: f32) {
+        self.step(camera.get_size(), viewport.height, dt);
+
+        let position = self.view.position + self.view.offset;
+        camera.set_position(Vec3::new(position.x, position.y, camera.get_position().z));
+        camera.set_rotation(self.view.rotation + self.view.rotation_offset);
+        camera.recalculate_matrix();
+    }
+
+    fn step(&mut self, view_size: Vec2, viewport_height: f32, dt: f32) {
+        self.view.half_size = view_size * 0.5;
+        self.view.pixels_per_unit = viewport_height / view_size.y;
+        self.view.offset = glm::vec2(0.0, 0.0);
+        self.view.rotation_offset = 0.0;
+        self.view.goal = self.view.target;
+
+        for behavior in self.behaviors.iter_mut() {
+            behavior.update(&mut self.view, dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glm;
+    use crate::renderer::camera::Bounds;
+    use crate::renderer::camera_rig::{CameraRig, ClampToBounds, DeadZone, Follow, PixelSnap, Shake};
+
+    #[test]
+    fn behaviors_compose_in_order() {
+        let mut rig = CameraRig::new(glm::vec2(0.0, 0.0))
+            .with(DeadZone::new(glm::vec2(1.0, 1.0)))
+            .with(Follow::new(0.0))
+            .with(ClampToBounds::new(Bounds::new(glm::vec2(-5.0, -5.0), glm::vec2(5.0, 5.0))));
+        let view_size = glm::vec2(4.0, 2.0);
+
+        // inside the dead zone the camera stays put
+        rig.set_target(glm::vec2(0.4, -0.4), glm::vec2(0.0, 0.0));
+        rig.step(view_size, 100.0, 0.1);
+        assert_eq!(rig.get_view().position, glm::vec2(0.0, 0.0));
+
+        // past it the camera follows until the target is back on the edge
+        rig.set_target(glm::vec2(2.0, 0.0), glm::vec2(0.0, 0.0));
+        rig.step(view_size, 100.0, 0.1);
+        assert_eq!(rig.get_view().position, glm::vec2(1.5, 0.0));
+
+        // and never shows anything outside the level
+        rig.set_target(glm::vec2(20.0, 20.0), glm::vec2(0.0, 0.0));
+        rig.step(view_size, 100.0, 0.1);
+        assert_eq!(rig.get_view().position, glm::vec2(3.0, 4.0));
+    }
+
+    #[test]
+    fn damped_cameras_reach_the_edge_of_the_dead_zone() {
+        let mut rig = CameraRig::new(glm::vec2(0.0, 0.0))
+            .with(DeadZone::new(glm::vec2(1.0, 1.0)))
+            .with(Follow::new(5.0));
+        rig.set_target(glm::vec2(2.0, 0.0), glm::vec2(0.0, 0.0));
+        for _ in 0..100 {
+            rig.step(glm::vec2(4.0, 2.0), 100.0, 0.1);
+        }
+        assert!((rig.get_view().position.x - 1.5).abs() < 1e-3);
+        assert_eq!(rig.get_view().target, glm::vec2(2.0, 0.0));
+    }
+
+    #[test]
+    fn shake_wears_off_and_snapping_lands_on_pixels() {
+        let mut rig = CameraRig::new(glm::vec2(0.013, 0.0))
+            .with(Shake::new(glm::vec2(0.5, 0.5), 0.1).with_recovery(2.0))
+            .with(PixelSnap);
+        rig.add_trauma(0.8);
+
+        rig.step(glm::vec2(2.0, 2.0), 100.0, 0.1);
+        let view = *rig.get_view();
+        assert!((view.trauma - 0.6).abs() < 1e-5);
+        let pixels = (view.position + view.offset) * 50.0;
+        assert!(glm::distance(&pixels, &glm::round(&pixels)) < 1e-3);
+
+        for _ in 0..10 {
+            rig.step(glm::vec2(2.0, 2.0), 100.0, 0.1);
+        }
+        assert_eq!(rig.get_view().trauma, 0.0);
+        assert_eq!(rig.get_view().rotation_offset, 0.0);
+    }
+}
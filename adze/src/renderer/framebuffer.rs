@@ -0,0 +1,101 @@
+use glow::HasContext;
+
+use crate::renderer::resource::{self, GlResource};
+use crate::renderer::texture::{SamplerDesc, Texture};
+
+/// An offscreen render target with a color texture and a depth buffer. Cameras draw into it
+/// with `Renderer::begin_view` and its color texture can then be drawn like any other, for
+/// minimaps, mirrors or picture in picture.
+pub struct Framebuffer {
+    renderer_id: glow::Framebuffer,
+    color: Texture,
+    depth: glow::Renderbuffer,
+    sampler: SamplerDesc
+}
+
+unsafe fn create_attachments(gl: &glow::Context, renderer_id: glow::Framebuffer, width: u32, height: u32, sampler: SamplerDesc) -> (Texture, glow::Renderbuffer) {
+    let color = Texture::from_dimensions(gl, width, height, sampler);
+
+    let depth = gl.create_renderbuffer().unwrap();
+    resource::track(GlResource::Renderbuffer(depth));
+    gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+    gl.renderbuffer_storage(glow::RENDERBUFFER, glow::DEPTH_COMPONENT24, width as i32, height as i32);
+    gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(renderer_id));
+    gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, color.get_renderer_id(), 0);
+    gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::DEPTH_ATTACHMENT, glow::RENDERBUFFER, Some(depth));
+    let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+    assert_eq!(status, glow::FRAMEBUFFER_COMPLETE, "framebuffer is incomplete");
+    gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+    (color, depth)
+}
+
+impl Framebuffer {
+    pub fn new(gl: &glow::Context, width: u32, height: u32, sampler: SamplerDesc) -> Framebuffer {
+        unsafe {
+            let renderer_id = gl.create_framebuffer().unwrap();
+            resource::track(GlResource::Framebuffer(renderer_id));
+            let (color, depth) = create_attachments(gl, renderer_id, width.max(1), height.max(1), sampler);
+
+            Framebuffer {
+                renderer_id,
+                color,
+                depth,
+                sampler
+            }
+        }
+    }
+
+    /// Recreates the attachments at a new size, their contents are lost.
+    pub fn resize(&mut self, gl: &glow::Context, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        if width == self.get_width() && height == self.get_height() {
+            return;
+        }
+
+        resource::release(GlResource::Renderbuffer(self.depth));
+        unsafe {
+            let (color, depth) = create_attachments(gl, self.renderer_id, width, height, self.sampler);
+            self.color = color;
+            self.depth = depth;
+        }
+    }
+
+    pub fn get_renderer_id(&self) -> glow::Framebuffer {
+        self.renderer_id
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.color.get_width()
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.color.get_height()
+    }
+
+    /// What was drawn into the framebuffer, upside up when drawn as a quad.
+    pub fn get_color_texture(&self) -> &Texture {
+        &self.color
+    }
+
+    pub fn bind(&self, gl: &glow::Context) {
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.renderer_id));
+        }
+    }
+
+    pub fn unbind(&self, gl: &glow::Context) {
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        resource::release(GlResource::Renderbuffer(self.depth));
+        resource::release(GlResource::Framebuffer(self.renderer_id));
+    }
+}
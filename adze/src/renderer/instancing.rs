@@ -0,0 +1,121 @@
+use glow::HasContext;
+
+use crate::glm::{Mat4, Vec2, Vec4};
+use crate::renderer::buffer::{IndexBuffer, Vertex, VertexArray, VertexBuffer};
+use crate::renderer::shader::Shader;
+
+const INSTANCED_VS_SRC: &str = "
+        #version 330 core
+
+        layout(location = 0) in vec2 aposition;
+        layout(location = 1) in mat4 atransform;
+        layout(location = 5) in vec4 acolor;
+        layout(location = 6) in vec4 auv_rect;
+        layout(location = 7) in float atexture_index;
+
+        layout(std140) uniform SceneData {
+            mat4 uprojection_view;
+            vec2 uresolution;
+            float utime;
+        };
+
+        out vec2 vtexture_coordinate;
+        out vec4 vcolor;
+        out float vtexture_index;
+
+        void main() {
+            vtexture_coordinate = mix(auv_rect.xy, auv_rect.zw, aposition);
+            vtexture_index = atexture_index;
+            vcolor = acolor;
+            gl_Position = uprojection_view * atransform * vec4(aposition, 0.0, 1.0);
+        }
+";
+
+#[derive(Clone, Copy, Debug, Vertex)]
+#[repr(C)]
+struct UnitVertex {
+    position: Vec2
+}
+
+/// Per sprite data of the instanced path, one of these replaces the four `QuadVertex` the
+/// batcher builds for every quad.
+#[derive(Clone, Copy, Debug, Vertex)]
+#[repr(C)]
+#[vertex(divisor = 1)]
+pub(crate) struct SpriteInstance {
+    transform: Mat4,
+    color: Vec4,
+    uv_rect: Vec4,
+    texture_index: f32
+}
+
+impl SpriteInstance {
+    pub(crate) fn new(transform: Mat4, color: Vec4, uv_rect: Vec4, texture_index: f32) -> SpriteInstance {
+        SpriteInstance {
+            transform,
+            color,
+            uv_rect,
+            texture_index
+        }
+    }
+}
+
+/// Draws sprites as instances of one static unit quad.
+pub(crate) struct InstancedQuads {
+    vertex_array: VertexArray,
+    // only read by the gpu, owned here so it lives as long as the vertex array
+    _unit_quad: VertexBuffer<UnitVertex>,
+    instance_buffer: VertexBuffer<SpriteInstance>,
+    shader: Shader,
+    instances: Vec<SpriteInstance>
+}
+
+impl InstancedQuads {
+    pub(crate) fn new(gl: &glow::Context, fragment_src: &str, max_instances: usize) -> InstancedQuads {
+        let unit_quad = VertexBuffer::new(gl, &[
+            UnitVertex { position: Vec2::new(0.0, 0.0) },
+            UnitVertex { position: Vec2::new(1.0, 0.0) },
+            UnitVertex { position: Vec2::new(1.0, 1.0) },
+            UnitVertex { position: Vec2::new(0.0, 1.0) },
+        ]);
+        let instance_buffer = VertexBuffer::with_capacity(gl, max_instances);
+
+        let mut vertex_array = VertexArray::new(gl, IndexBuffer::new(gl, vec![0, 1, 2, 2, 3, 0]));
+        vertex_array.add_vertex_buffer(gl, &unit_quad);
+        vertex_array.add_vertex_buffer(gl, &instance_buffer);
+
+        InstancedQuads {
+            vertex_array,
+            _unit_quad: unit_quad,
+            instance_buffer,
+            shader: Shader::new(gl, INSTANCED_VS_SRC, fragment_src),
+            instances: Vec::with_capacity(max_instances)
+        }
+    }
+
+    pub(crate) fn get_shader(&self) -> &Shader {
+        &self.shader
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.instances.len() >= self.instance_buffer.get_capacity()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, instance: SpriteInstance) {
+        self.instances.push(instance);
+    }
+
+    pub(crate) fn draw(&mut self, gl: &glow::Context) {
+        self.instance_buffer.stream_vertices(gl, &self.instances);
+        self.shader.bind(gl);
+        self.vertex_array.bind(gl);
+        unsafe {
+            gl.draw_elements_instanced(glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0, self.instances.len() as i32);
+        }
+        self.instances.clear();
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use glow::HasContext;
+
+use crate::glm::{self, Vec2, Vec3};
+use crate::renderer::buffer::{IndexBuffer, Vertex, VertexArray, VertexBuffer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Vertex)]
+#[repr(C)]
+pub struct MeshVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub texture_coordinate: Vec2
+}
+
+/// The vertices and triangles of a mesh before they are uploaded, safe to build on the
+/// loader threads.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>
+}
+
+/// Resolves a 1 based OBJ index, negative ones count back from the last element read.
+fn resolve_index(index: &str, len: usize, line: usize) -> Result<usize, String> {
+    let index: i64 = index.parse().map_err(|_| format!("line {}: invalid index {}", line, index))?;
+    let resolved = if index < 0 { len as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("line {}: index {} is out of range", line, index));
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_floats<'a>(parts: impl Iterator<Item = &'a str>, count: usize, line: usize) -> Result<Vec<f32>, String> {
+    let values = parts.take(count)
+        .map(|part| part.parse::<f32>().map_err(|_| format!("line {}: invalid number {}", line, part)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    if values.len() < count {
+        return Err(format!("line {}: expected {} numbers", line, count));
+    }
+    Ok(values)
+}
+
+impl MeshData {
+    /// Parses the geometry of a Wavefront OBJ file. Polygons are split into triangles,
+    /// materials and groups are ignored and normals missing from the file are smoothed from
+    /// the faces around each vertex.
+    pub fn from_obj(source: &str) -> Result<MeshData, String> {
+        let mut positions = vec![];
+        let mut texture_coordinates = vec![];
+        let mut normals = vec![];
+
+        let mut data = MeshData::default();
+        // a vertex for every distinct position, texture coordinate and normal combination
+        let mut vertex_ids: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+        let mut needs_normals = false;
+
+        for (number, line) in source.lines().enumerate() {
+            let number = number + 1;
+            let line = line.split('#').next().unwrap_or("");
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("v") => {
+                    let v = parse_floats(parts, 3, number)?;
+                    positions.push(glm::vec3(v[0], v[1], v[2]));
+                }
+                Some("vt") => {
+                    let v = parse_floats(parts, 2, number)?;
+                    texture_coordinates.push(glm::vec2(v[0], v[1]));
+                }
+                Some("vn") => {
+                    let v = parse_floats(parts, 3, number)?;
+                    normals.push(glm::vec3(v[0], v[1], v[2]));
+                }
+                Some("f") => {
+                    let mut face = vec![];
+                    for corner in parts {
+                        let mut indices = corner.split('/');
+                        let position = resolve_index(indices.next().unwrap_or(""), positions.len(), number)?;
+                        let texture_coordinate = match indices.next() {
+                            Some(index) if !index.is_empty() => Some(resolve_index(index, texture_coordinates.len(), number)?),
+                            _ => None
+                        };
+                        let normal = match indices.next() {
+                            Some(index) if !index.is_empty() => Some(resolve_index(index, normals.len(), number)?),
+                            _ => None
+                        };
+                        needs_normals |= normal.is_none();
+
+                        let key = (position, texture_coordinate, normal);
+                        let id = match vertex_ids.get(&key) {
+                            Some(id) => *id,
+                            None => {
+                                let id = data.vertices.len() as u32;
+                                data.vertices.push(MeshVertex {
+                                    position: positions[position],
+                                    normal: normal.map_or(glm::vec3(0.0, 0.0, 0.0), |normal| normals[normal]),
+                                    texture_coordinate: texture_coordinate.map_or(glm::vec2(0.0, 0.0), |uv| texture_coordinates[uv])
+                                });
+                                vertex_ids.insert(key, id);
+                                id
+                            }
+                        };
+                        face.push(id);
+                    }
+
+                    if face.len() < 3 {
+                        return Err(format!("line {}: faces need at least 3 vertices", number));
+                    }
+                    for i in 1..face.len() - 1 {
+                        data.indices.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if needs_normals {
+            data.compute_normals();
+        }
+        Ok(data)
+    }
+
+    /// Replaces the normals of vertices without one by the average of the faces they are
+    /// part of, weighted by area.
+    fn compute_normals(&mut self) {
+        let missing: Vec<bool> = self.vertices.iter().map(|vertex| vertex.normal == glm::vec3(0.0, 0.0, 0.0)).collect();
+        for triangle in self.indices.chunks(3) {
+            let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let normal = glm::cross(
+                &(self.vertices[b].position - self.vertices[a].position),
+                &(self.vertices[c].position - self.vertices[a].position)
+            );
+            for i in [a, b, c] {
+                if missing[i] {
+                    self.vertices[i].normal += normal;
+                }
+            }
+        }
+        for (vertex, missing) in self.vertices.iter_mut().zip(missing) {
+            if missing && vertex.normal != glm::vec3(0.0, 0.0, 0.0) {
+                vertex.normal = vertex.normal.normalize();
+            }
+        }
+    }
+
+    /// A cube one unit across centered on the origin, with flat faces.
+    pub fn cube() -> MeshData {
+        let mut data = MeshData::default();
+        let faces = [
+            (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 0.0, -1.0)),
+            (glm::vec3(-1.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+            (glm::vec3(0.0, 1.0, 0.0), glm::vec3(1.0, 0.0, 0.0)),
+            (glm::vec3(0.0, -1.0, 0.0), glm::vec3(1.0, 0.0, 0.0)),
+            (glm::vec3(0.0, 0.0, 1.0), glm::vec3(1.0, 0.0, 0.0)),
+            (glm::vec3(0.0, 0.0, -1.0), glm::vec3(-1.0, 0.0, 0.0))
+        ];
+        for (normal, right) in faces.iter() {
+            let up = glm::cross(normal, right);
+            let first = data.vertices.len() as u32;
+            for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                data.vertices.push(MeshVertex {
+                    position: (normal + right * (x * 2.0 - 1.0) + up * (y * 2.0 - 1.0)) * 0.5,
+                    normal: *normal,
+                    texture_coordinate: glm::vec2(x, y)
+                });
+            }
+            data.indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+        }
+        data
+    }
+
+    /// The corners of the box around every vertex.
+    pub fn get_bounds(&self) -> (Vec3, Vec3) {
+        let mut min = glm::vec3(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = glm::vec3(f32::MIN, f32::MIN, f32::MIN);
+        for vertex in self.vertices.iter() {
+            min = glm::min2(&min, &vertex.position);
+            max = glm::max2(&max, &vertex.position);
+        }
+        (min, max)
+    }
+}
+
+/// A mesh uploaded to the gpu, drawn with `Renderer::draw_mesh`.
+pub struct Mesh {
+    vertex_array: VertexArray,
+    // only read by the gpu, owned here so it lives as long as the vertex array
+    _vertex_buffer: VertexBuffer<MeshVertex>,
+    bounds: (Vec3, Vec3)
+}
+
+impl Mesh {
+    pub fn new(gl: &glow::Context, data: &MeshData) -> Mesh {
+        let vertex_buffer = VertexBuffer::new(gl, &data.vertices);
+        let mut vertex_array = VertexArray::new(gl, IndexBuffer::new(gl, data.indices.clone()));
+        vertex_array.add_vertex_buffer(gl, &vertex_buffer);
+
+        Mesh {
+            vertex_array,
+            _vertex_buffer: vertex_buffer,
+            bounds: data.get_bounds()
+        }
+    }
+
+    pub fn get_index_count(&self) -> usize {
+        self.vertex_array.get_indices_len()
+    }
+
+    pub fn get_bounds(&self) -> (Vec3, Vec3) {
+        self.bounds
+    }
+
+    pub(crate) fn draw(&self, gl: &glow::Context) {
+        self.vertex_array.bind(gl);
+        unsafe {
+            gl.draw_elements(glow::TRIANGLES, self.get_index_count() as i32, glow::UNSIGNED_INT, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glm;
+    use crate::renderer::mesh::MeshData;
+
+    #[test]
+    fn parses_obj_polygons_and_shares_vertices() {
+        let source = "
+            # a quad made of two corners of a cube
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 1
+            vn 0 0 1
+            f 1/1/1 2/1/1 3/2/1 4/2/1
+            f -4 -2 -1
+        ";
+        let data = MeshData::from_obj(source).unwrap();
+
+        // the quad is split into two triangles, the last face reuses positions without
+        // texture coordinates or normals so it gets its own vertices with computed normals
+        assert_eq!(data.indices.len(), 9);
+        assert_eq!(data.vertices.len(), 7);
+        assert_eq!(data.vertices[2].texture_coordinate, glm::vec2(1.0, 1.0));
+        assert!(glm::distance(&data.vertices[6].normal, &glm::vec3(0.0, 0.0, 1.0)) < 1e-5);
+        assert_eq!(data.get_bounds(), (glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 1.0, 0.0)));
+
+        assert!(MeshData::from_obj("v 0 0 0\nf 1 2 3").is_err());
+        assert_eq!(MeshData::cube().indices.len(), 36);
+    }
+}
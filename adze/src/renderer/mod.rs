@@ -1,63 +1,79 @@
 use glow::{HasContext};
 use nalgebra_glm as glm;
 
-use camera::OrthographicCamera;
+use camera::{Camera, Viewport};
+use framebuffer::Framebuffer;
+use mesh::Mesh;
 use shader::Shader;
-use texture::Texture;
+use texture::{SamplerDesc, Texture};
 
-use crate::glm::{Vec2, Vec3, Vec4};
-use crate::renderer::buffer::{BufferElement, BufferLayout, IndexBuffer, ShaderDataType, VertexArray, VertexBuffer};
+use crate::asset::{AssetServer, Handle};
+use crate::glm::{Mat4, Vec2, Vec3, Vec4};
 use core::mem;
 
+use crate::renderer::buffer::{BufferElement, BufferLayout, IndexBuffer, ShaderDataType, UniformBuffer, Vertex, VertexArray, VertexBuffer};
+use crate::renderer::instancing::{InstancedQuads, SpriteInstance};
+use crate::renderer::resource::ContextGuard;
+use crate::renderer::sorting::{QuadCommand, SortKey};
+use crate::renderer::view::{ClearMode, LayerMask, ViewSettings, DEFAULT_LAYER};
+
 pub mod buffer;
 pub mod camera;
+pub mod camera_rig;
+pub mod framebuffer;
+pub mod mesh;
 pub mod shader;
+pub mod sorting;
 pub mod texture;
+pub mod view;
+mod instancing;
+mod resource;
 
-// Shader sources
-const TEXTURE_VS_SRC: &str = "
-        #version 330 core
-
-        layout(location = 0) in vec3 aposition;
-        layout(location = 1) in vec4 acolor;
-        layout(location = 2) in vec2 atexture_coordinate;
-        layout(location = 3) in float atexture_index;
-
-        uniform mat4 uprojection_view;
-
-        out vec2 vtexture_coordinate;
-        out vec4 vcolor;
-        out float vtexture_index;
-
-        void main() {
-            vtexture_coordinate = atexture_coordinate;
-            vtexture_index = atexture_index;
-            vcolor = acolor;
-            gl_Position = uprojection_view * vec4(aposition, 1.0);
-        }
-";
+/// The batched sprite shader, its fragment stage is shared with the instanced path.
+const SPRITE_SHADER_PATH: &str = "engine://shaders/sprite.glsl";
+const MESH_SHADER_PATH: &str = "engine://shaders/mesh.glsl";
 
-const TEXTURE_FS_SRC: &str = "
-        #version 330 core
-        layout(location = 0) out vec4 color;
+const MAX_TEXTURE_SLOTS: usize = 16;
 
-        in vec4 vcolor;
-        in vec2 vtexture_coordinate;
-        in float vtexture_index;
+/// Binding point of the `SceneData` uniform block. Shaders declaring the block should bind it
+/// here with `Shader::bind_uniform_block` to receive the camera, resolution and time.
+pub const SCENE_DATA_BINDING: u32 = 0;
 
-        uniform sampler2D utextures[16];
+/// Options fixed when the renderer is created.
+#[derive(Clone, Copy, Debug)]
+pub struct RendererSettings {
+    /// Quads drawn per draw call, at least one. Larger batches mean fewer draw calls but more
+    /// memory and bigger uploads for every flush.
+    pub max_quads: usize,
+    /// Blends with premultiplied alpha. Textures should then be loaded with
+    /// `Texture::set_premultiply_alpha`, quad colors are premultiplied by the renderer.
+    pub premultiplied_alpha: bool,
+    /// Draws at this many pixels and scales the result up to the window, see
+    /// `Renderer::set_virtual_resolution`.
+    pub virtual_resolution: Option<(u32, u32)>
+}
 
-        void main() {
-            color = texture(utextures[int(vtexture_index)], vtexture_coordinate) * vcolor;
+impl Default for RendererSettings {
+    fn default() -> Self {
+        RendererSettings {
+            max_quads: 10000,
+            premultiplied_alpha: false,
+            virtual_resolution: None
         }
-";
+    }
+}
 
-const MAX_QUADS: usize = 10000;
-const MAX_VERTICES: usize = MAX_QUADS * 4;
-const MAX_INDICES: usize = MAX_QUADS * 6;
-const MAX_TEXTURE_SLOTS: usize = 16;
+/// How submitted quads are turned into draw calls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BatchMode {
+    /// Builds four vertices per quad on the cpu, cheapest for small scenes.
+    Batched,
+    /// Uploads one instance per quad drawn over a static unit quad, for large sprite counts.
+    Instanced
+}
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Vertex)]
+#[repr(C)]
 pub struct QuadVertex {
     position: Vec3,
     color: Vec4,
@@ -65,32 +81,58 @@ pub struct QuadVertex {
     texture_index: f32
 }
 
+struct MeshCommand {
+    mesh: Handle<Mesh>,
+    transform: Mat4,
+    color: Vec4,
+    texture: glow::Texture
+}
+
 pub struct Renderer {
-    gl: glow::Context,
     vertex_array: VertexArray,
+    vertex_buffer: VertexBuffer<QuadVertex>,
     shader: Shader,
+    scene_data: UniformBuffer,
+    resolution: Vec2,
+    time: f32,
     quad_vertices: Vec<QuadVertex>,
     index_count: i32,
-    textures: Vec<glow::Texture>
+    max_quads: usize,
+    premultiplied_alpha: bool,
+    instanced_quads: InstancedQuads,
+    batch_mode: BatchMode,
+    quad_commands: Vec<QuadCommand>,
+    sort_key: SortKey,
+    render_layers: LayerMask,
+    culling_mask: LayerMask,
+    // whether the current view draws into a framebuffer instead of the window
+    drawing_offscreen: bool,
+    // what views without a target draw into when a virtual resolution is set
+    virtual_screen: Option<Framebuffer>,
+    mesh_shader: Shader,
+    mesh_commands: Vec<MeshCommand>,
+    light_direction: Vec3,
+    ambient_light: f32,
+    white_texture: Texture,
+    textures: Vec<glow::Texture>,
+    asset_server: AssetServer,
+    // declared last so it is dropped after, and deletes, everything above
+    gl: ContextGuard
 }
 
 impl Renderer {
-    pub fn new(gl: glow::Context) -> Renderer {
-        let shader = Shader::new(&gl, TEXTURE_VS_SRC, TEXTURE_FS_SRC);
+    pub fn new(gl: glow::Context, settings: RendererSettings, asset_server: AssetServer) -> Renderer {
+        let sprite_source = asset_server.get_vfs().read_to_string(SPRITE_SHADER_PATH).unwrap();
+        let (sprite_vs_src, sprite_fs_src) = shader::split_stages(&sprite_source).unwrap();
+        let shader = Shader::new(&gl, &sprite_vs_src, &sprite_fs_src);
         shader.bind(&gl);
 
-        let layout = BufferLayout::new(
-            vec![
-                BufferElement::new("aposition".parse().unwrap(), ShaderDataType::Float3, false),
-                BufferElement::new("acolor".parse().unwrap(), ShaderDataType::Float4, false),
-                BufferElement::new("atexture_coordinate".parse().unwrap(), ShaderDataType::Float2, false),
-                BufferElement::new("atexture_index".parse().unwrap(), ShaderDataType::Float1, false),
-            ]
-        );
+        // an empty batch would never have room for a quad
+        let max_quads = settings.max_quads.max(1);
 
         let mut offset = 0;
         let mut indices = Vec::new();
-        for _ in (0..MAX_INDICES).step_by(6) {
+        for _ in 0..max_quads {
             indices.push(offset);
             indices.push(offset + 1);
             indices.push(offset + 2);
@@ -103,183 +145,555 @@ impl Renderer {
         }
 
         let index_buffer = IndexBuffer::new(&gl, indices);
-        let vertex_buffer = VertexBuffer::from_size(&gl, (MAX_VERTICES * mem::size_of::<QuadVertex>()) as i32, layout);
-        let vertex_array = VertexArray::new(&gl, index_buffer, vertex_buffer);
+        let vertex_buffer = VertexBuffer::with_capacity(&gl, max_quads * 4);
+        let mut vertex_array = VertexArray::new(&gl, index_buffer);
+        vertex_array.add_vertex_buffer(&gl, &vertex_buffer);
 
-        let white_texture = Texture::from_data(&gl, vec![255_u8, 255_u8, 255_u8, 255_u8], 1, 1, glow::RGBA8, glow::RGBA);
+        let white_texture = Texture::from_data(&gl, vec![255_u8, 255_u8, 255_u8, 255_u8], 1, 1, glow::RGBA8, glow::RGBA, SamplerDesc::default());
 
         // let mut white_texture = Texture::from_dimensions(&gl,1, 1);
         // white_texture.set_data(&gl, vec![255_u8, 255_u8, 255_u8, 255_u8]);
-        let white_texture_renderer_id = white_texture.get_renderer_id().unwrap();
 
         let mut samplers: Vec<i32> = Vec::new();
         for i in 0..MAX_TEXTURE_SLOTS {
             samplers.push(i as i32);
         }
 
-        shader.upload_uniform_int_array(&gl, "utextures", samplers);
+        shader.upload_uniform_int_array(&gl, "utextures", samplers.clone());
+        shader.bind_uniform_block(&gl, "SceneData", SCENE_DATA_BINDING);
+
+        let instanced_quads = InstancedQuads::new(&gl, &sprite_fs_src, max_quads);
+        instanced_quads.get_shader().bind(&gl);
+        instanced_quads.get_shader().upload_uniform_int_array(&gl, "utextures", samplers);
+        instanced_quads.get_shader().bind_uniform_block(&gl, "SceneData", SCENE_DATA_BINDING);
+
+        let mesh_source = asset_server.get_vfs().read_to_string(MESH_SHADER_PATH).unwrap();
+        let mesh_shader = Shader::from_source(&gl, &mesh_source).unwrap();
+        mesh_shader.bind(&gl);
+        mesh_shader.upload_uniform_integer1(&gl, "utexture", 0);
+        mesh_shader.bind_uniform_block(&gl, "SceneData", SCENE_DATA_BINDING);
+
+        let scene_data = UniformBuffer::new(
+            &gl,
+            BufferLayout::new_std140(
+                vec![
+                    BufferElement::new("uprojection_view".parse().unwrap(), ShaderDataType::Matrix4, false),
+                    BufferElement::new("uresolution".parse().unwrap(), ShaderDataType::Float2, false),
+                    BufferElement::new("utime".parse().unwrap(), ShaderDataType::Float1, false),
+                ]
+            ),
+            SCENE_DATA_BINDING
+        );
 
-        Renderer::init(&gl);
+        Renderer::init(&gl, &settings);
+        asset_server.set_premultiply_alpha(settings.premultiplied_alpha);
 
-        Renderer {
-            gl,
+        let mut renderer = Renderer {
             vertex_array,
+            vertex_buffer,
             shader,
-            quad_vertices: vec![],
+            scene_data,
+            resolution: glm::vec2(0.0, 0.0),
+            time: 0.0,
+            quad_vertices: Vec::with_capacity(max_quads * 4),
             index_count: 0,
-            textures: vec![white_texture_renderer_id]
-        }
+            max_quads,
+            premultiplied_alpha: settings.premultiplied_alpha,
+            instanced_quads,
+            batch_mode: BatchMode::Batched,
+            quad_commands: vec![],
+            sort_key: SortKey::default(),
+            render_layers: DEFAULT_LAYER,
+            culling_mask: view::ALL_LAYERS,
+            drawing_offscreen: false,
+            virtual_screen: None,
+            mesh_shader,
+            mesh_commands: vec![],
+            light_direction: glm::normalize(&glm::vec3(-0.3, -1.0, -0.5)),
+            ambient_light: 0.3,
+            textures: vec![white_texture.get_renderer_id().unwrap()],
+            white_texture,
+            asset_server,
+            gl: ContextGuard::new(gl)
+        };
+        renderer.set_virtual_resolution(settings.virtual_resolution);
+        renderer
     }
 
-    fn init(gl: &glow::Context) {
+    fn init(gl: &glow::Context, settings: &RendererSettings) {
         unsafe {
             gl.enable(glow::BLEND);
-            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
-
-            gl.enable(glow::DEPTH_TEST);
+            if settings.premultiplied_alpha {
+                gl.blend_func(glow::ONE, glow::ONE_MINUS_SRC_ALPHA);
+            } else {
+                gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+            }
         }
     }
 
-    pub fn begin(&mut self, camera: &OrthographicCamera) {
-        self.shader.bind(&self.gl);
-        self.shader.upload_uniform_mat4(&self.gl, "uprojection_view",  &camera.get_projection_view());
+    /// Starts drawing the whole window from `camera`, an `OrthographicCamera` for 2D or a
+    /// `PerspectiveCamera` for 3D.
+    pub fn begin<C: Camera + ?Sized>(&mut self, camera: &C) {
+        self.begin_view(camera, &ViewSettings::default(), None);
+    }
 
+    /// Starts drawing from `camera` into part of the window, or of `target` when given.
+    /// Every view is drawn with its own `begin_view` and `end`, so a frame can hold split
+    /// screens, minimaps and picture in picture. Only draws on render layers in the view's
+    /// culling mask are kept.
+    pub fn begin_view<C: Camera + ?Sized>(&mut self, camera: &C, settings: &ViewSettings, target: Option<&Framebuffer>) {
+        self.asset_server.update(&self.gl);
+        resource::collect(&self.gl);
+
+        let target = target.or(self.virtual_screen.as_ref());
+        let target_size = match target {
+            Some(framebuffer) => glm::vec2(framebuffer.get_width() as f32, framebuffer.get_height() as f32),
+            None => self.resolution
+        };
+        let viewport = settings.viewport.unwrap_or_else(|| Viewport::from_size(target_size));
+        let (x, y, width, height) = view::to_gl_rect(&viewport, target_size.y);
+
+        unsafe {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, target.map(|framebuffer| framebuffer.get_renderer_id()));
+            self.gl.viewport(x, y, width, height);
+
+            if settings.clear != ClearMode::None {
+                // clears only touch the view's own part of the target
+                self.gl.enable(glow::SCISSOR_TEST);
+                self.gl.scissor(x, y, width, height);
+                self.gl.depth_mask(true);
+                match settings.clear {
+                    ClearMode::Color(color) => {
+                        self.gl.clear_color(color.x, color.y, color.z, color.w);
+                        self.gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+                    }
+                    ClearMode::Depth => self.gl.clear(glow::DEPTH_BUFFER_BIT),
+                    ClearMode::None => {}
+                }
+                self.gl.disable(glow::SCISSOR_TEST);
+            }
+        }
+        self.drawing_offscreen = target.is_some();
+
+        self.scene_data.set_matrix4("uprojection_view", &camera.get_projection_view());
+        self.scene_data.set_float2("uresolution", glm::vec2(viewport.width, viewport.height));
+        self.scene_data.set_float1("utime", self.time);
+        self.scene_data.upload(&self.gl);
+
+        self.quad_commands.clear();
+        self.mesh_commands.clear();
+        self.sort_key = SortKey::default();
+        self.render_layers = DEFAULT_LAYER;
+        self.culling_mask = settings.culling_mask;
         self.reset();
     }
 
+    /// Draws the meshes submitted since `begin`, then sorts the quads and draws them.
     pub fn end(&mut self) {
-        self.vertex_array.set_vertices(&self.gl, &self.quad_vertices);
+        let drew_meshes = self.draw_meshes();
+
+        let mut quad_commands = mem::take(&mut self.quad_commands);
+        sorting::sort_quads(&mut quad_commands);
+
+        // sprites are drawn back to front, writing depth would let translucent quads clip
+        // whatever is drawn behind them later. They are still tested against the meshes so
+        // props can stand in front of and behind them.
+        unsafe {
+            if drew_meshes {
+                self.gl.enable(glow::DEPTH_TEST);
+                self.gl.depth_func(glow::LEQUAL);
+            } else {
+                self.gl.disable(glow::DEPTH_TEST);
+            }
+            self.gl.depth_mask(false);
+        }
+
+        let batch_mode = self.batch_mode;
+        for command in quad_commands.iter() {
+            if command.batch_mode != self.batch_mode {
+                self.flush();
+                self.batch_mode = command.batch_mode;
+            }
+            self.submit_quad(&command.transform, command.color, command.texture);
+        }
         self.flush();
+        self.batch_mode = batch_mode;
+
+        unsafe {
+            self.gl.depth_mask(true);
+
+            // back to the whole window for whatever is drawn after the view, like the ui
+            if self.drawing_offscreen {
+                self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            }
+            self.gl.viewport(0, 0, self.resolution.x as i32, self.resolution.y as i32);
+        }
+        self.drawing_offscreen = false;
+
+        quad_commands.clear();
+        self.quad_commands = quad_commands;
     }
 
-    fn reset(&mut self) {
-        for _ in 1..self.textures.len() {
-            self.textures.pop();
+    /// Sets the render layers the draws after this call are on, until the next call or the
+    /// next `begin`. Views whose culling mask has none of them skip the draws.
+    pub fn set_render_layers(&mut self, render_layers: LayerMask) {
+        self.render_layers = render_layers;
+    }
+
+    pub fn get_render_layers(&self) -> LayerMask {
+        self.render_layers
+    }
+
+    fn is_culled(&self) -> bool {
+        self.render_layers & self.culling_mask == 0
+    }
+
+    /// The opaque forward pass, drawn with depth testing and writing before any sprites.
+    fn draw_meshes(&mut self) -> bool {
+        if self.mesh_commands.is_empty() {
+            return false;
+        }
+        let mesh_commands = mem::take(&mut self.mesh_commands);
+        let mut drew_meshes = false;
+
+        unsafe {
+            self.gl.enable(glow::DEPTH_TEST);
+            self.gl.depth_func(glow::LESS);
+            self.gl.depth_mask(true);
+            self.gl.enable(glow::CULL_FACE);
         }
-        self.quad_vertices = Vec::new();
+
+        self.mesh_shader.bind(&self.gl);
+        self.mesh_shader.upload_uniform_float3(&self.gl, "ulight_direction", self.light_direction);
+        self.mesh_shader.upload_uniform_float1(&self.gl, "uambient", self.ambient_light);
+        for command in mesh_commands.iter() {
+            let mesh = match self.asset_server.get_mesh(&command.mesh) {
+                Some(mesh) => mesh,
+                None => continue
+            };
+            let normal_matrix = glm::mat4_to_mat3(&glm::transpose(&glm::inverse(&command.transform)));
+            self.mesh_shader.upload_uniform_mat4(&self.gl, "umodel", &command.transform);
+            self.mesh_shader.upload_uniform_matrix3(&self.gl, "unormal_matrix", &normal_matrix);
+            self.mesh_shader.upload_uniform_float4(&self.gl, "ucolor", command.color);
+            Texture::bind(&self.gl, command.texture, 0);
+            mesh.draw(&self.gl);
+            drew_meshes = true;
+        }
+
+        unsafe {
+            self.gl.disable(glow::CULL_FACE);
+        }
+
+        self.mesh_commands = mesh_commands;
+        self.mesh_commands.clear();
+        drew_meshes
+    }
+
+    /// Sets where the quads drawn after this call go in the draw order, until the next call
+    /// or the next `begin`.
+    pub fn set_sort_key(&mut self, sort_key: SortKey) {
+        self.sort_key = sort_key;
+    }
+
+    pub fn get_sort_key(&self) -> SortKey {
+        self.sort_key
+    }
+
+    /// Switches between the cpu batcher and the instanced path for the quads drawn after
+    /// this. Quads are only batched at `end`, each with the mode it was drawn with.
+    pub fn set_batch_mode(&mut self, batch_mode: BatchMode) {
+        self.batch_mode = batch_mode;
+    }
+
+    pub fn get_batch_mode(&self) -> BatchMode {
+        self.batch_mode
+    }
+
+    pub fn get_max_quads(&self) -> usize {
+        self.max_quads
+    }
+
+    fn reset(&mut self) {
+        self.textures.clear();
+        self.textures.push(self.white_texture.get_renderer_id().unwrap());
+        self.quad_vertices.clear();
         self.index_count = 0;
     }
 
     fn flush(&mut self) {
-        self.vertex_array.set_vertices(&self.gl, &self.quad_vertices);
-
         for (i, texture) in self.textures.iter().enumerate() {
             Texture::bind(&self.gl, *texture, i as u32);
         }
 
-        self.draw();
+        match self.batch_mode {
+            BatchMode::Batched if self.index_count > 0 => self.draw(),
+            BatchMode::Instanced if !self.instanced_quads.is_empty() => self.instanced_quads.draw(&self.gl),
+            _ => {}
+        }
         self.reset();
     }
 
-    fn draw(&self) {
+    fn draw(&mut self) {
+        self.vertex_buffer.stream_vertices(&self.gl, &self.quad_vertices);
+        self.shader.bind(&self.gl);
+        self.vertex_array.bind(&self.gl);
         unsafe {
             self.gl.draw_elements(glow::TRIANGLES, self.index_count, glow::UNSIGNED_INT, 0);
         }
     }
 
+    fn is_batch_full(&self) -> bool {
+        match self.batch_mode {
+            BatchMode::Batched => self.quad_vertices.len() / 4 >= self.max_quads,
+            BatchMode::Instanced => self.instanced_quads.is_full()
+        }
+    }
+
+    /// Finds the slot `texture` is bound to in the current batch, flushing first if it needs
+    /// a new slot and none are left.
+    fn get_texture_index(&mut self, texture: glow::Texture) -> f32 {
+        if let Some(index) = self.textures.iter().position(|t| *t == texture) {
+            return index as f32;
+        }
+
+        if self.textures.len() == MAX_TEXTURE_SLOTS {
+            self.flush();
+        }
+        self.textures.push(texture);
+        self.textures.len() as f32 - 1.0
+    }
+
+    fn submit_quad(&mut self, transform: &Mat4, color: Vec4, texture: glow::Texture) {
+        if self.is_batch_full() {
+            self.flush();
+        }
+
+        let texture_index = self.get_texture_index(texture);
+
+        match self.batch_mode {
+            BatchMode::Batched => self.push_quad_vertices(transform, color, texture_index),
+            BatchMode::Instanced => self.instanced_quads.push(SpriteInstance::new(*transform, color, glm::vec4(0.0, 0.0, 1.0, 1.0), texture_index))
+        }
+    }
+
     pub fn clear(&self) {
         // Clear the screen to black
         unsafe {
+            if let Some(virtual_screen) = &self.virtual_screen {
+                virtual_screen.bind(&self.gl);
+            }
             self.gl.clear_color(0.3, 0.3, 0.3, 1.0);
             self.gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
         }
     }
 
-    pub fn borrow_context(&self) -> &glow::Context {
-        &self.gl
+    /// Draws every view without a target at a fixed number of pixels, then scales the result
+    /// to the window by whole numbers with nearest sampling so pixel art stays crisp. The
+    /// space left around it is filled black. `None` draws straight to the window again.
+    pub fn set_virtual_resolution(&mut self, virtual_resolution: Option<(u32, u32)>) {
+        match (virtual_resolution, &mut self.virtual_screen) {
+            (Some((width, height)), Some(virtual_screen)) => virtual_screen.resize(&self.gl, width, height),
+            (Some((width, height)), None) => self.virtual_screen = Some(Framebuffer::new(&self.gl, width, height, SamplerDesc::nearest())),
+            (None, _) => self.virtual_screen = None
+        }
     }
 
-    pub fn set_viewport(&self, x: u32, y: u32, width: u32, height: u32) {
-        unsafe {
-            self.gl.viewport(x as i32, y as i32, width as i32, height as i32);
+    pub fn get_virtual_resolution(&self) -> Option<(u32, u32)> {
+        self.virtual_screen.as_ref().map(|virtual_screen| (virtual_screen.get_width(), virtual_screen.get_height()))
+    }
+
+    /// Where the frame ends up in the window, in window pixels: the letterboxed virtual
+    /// screen, or the whole window without a virtual resolution. Cameras convert the cursor
+    /// position with this.
+    pub fn get_screen_viewport(&self) -> Viewport {
+        match &self.virtual_screen {
+            Some(virtual_screen) => view::letterbox(self.resolution, glm::vec2(virtual_screen.get_width() as f32, virtual_screen.get_height() as f32)),
+            None => Viewport::from_size(self.resolution)
         }
     }
 
-    pub fn draw_flat_color_quad(&mut self, position: Vec3, size: Vec3, color: Vec4) {
-        if self.quad_vertices.len() + 4 > MAX_VERTICES as usize {
-            self.flush();
+    /// Scales the virtual screen up to the window. Called by the app once the frame's ticks
+    /// are done, does nothing without a virtual resolution.
+    pub fn present(&self) {
+        let virtual_screen = match &self.virtual_screen {
+            Some(virtual_screen) => virtual_screen,
+            None => return
+        };
+        let (x, y, width, height) = view::to_gl_rect(&self.get_screen_viewport(), self.resolution.y);
+
+        unsafe {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            self.gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+
+            self.gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(virtual_screen.get_renderer_id()));
+            self.gl.blit_framebuffer(
+                0, 0, virtual_screen.get_width() as i32, virtual_screen.get_height() as i32,
+                x, y, x + width, y + height,
+                glow::COLOR_BUFFER_BIT, glow::NEAREST
+            );
+            self.gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
         }
+    }
 
-        self.quad_vertices.push( QuadVertex {
-            position,
-            color,
-            texture_coordinate: glm::vec2(0.0, 0.0),
-            texture_index: 0.0
-        });
+    /// Seconds since the app started, exposed to shaders as `utime`.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
 
-        self.quad_vertices.push(QuadVertex {
-            position: glm::vec3(position.x + size.x, position.y, position.z),
-            color,
-            texture_coordinate: glm::vec2(1.0, 0.0),
-            texture_index: 0.0
-        });
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
 
-        self.quad_vertices.push(QuadVertex {
-            position: glm::vec3(position.x + size.x, position.y + size.y, position.z),
-            color,
-            texture_coordinate: glm::vec2(1.0, 1.0),
-            texture_index: 0.0
-        });
+    /// The direction the light lighting meshes shines in.
+    pub fn set_light_direction(&mut self, direction: Vec3) {
+        self.light_direction = glm::normalize(&direction);
+    }
 
-        self.quad_vertices.push(QuadVertex {
-            position: glm::vec3(position.x, position.y + size.y, position.z),
-            color,
-            texture_coordinate: glm::vec2(0.0, 1.0),
-            texture_index: 0.0
-        });
+    pub fn get_light_direction(&self) -> Vec3 {
+        self.light_direction
+    }
 
-        self.index_count += 6;
+    /// How bright the sides of meshes facing away from the light are, from zero to one.
+    pub fn set_ambient_light(&mut self, ambient_light: f32) {
+        self.ambient_light = ambient_light;
     }
 
-    pub fn draw_quad(&mut self, position: Vec3, size: Vec2, texture: &mut Texture) {
-        if texture.get_renderer_id() == None {
-            texture.init(&self.gl);
+    pub fn get_ambient_light(&self) -> f32 {
+        self.ambient_light
+    }
+
+    /// The size in pixels views without a target draw into, the virtual resolution when
+    /// one is set.
+    pub fn get_resolution(&self) -> Vec2 {
+        match &self.virtual_screen {
+            Some(virtual_screen) => glm::vec2(virtual_screen.get_width() as f32, virtual_screen.get_height() as f32),
+            None => self.resolution
         }
+    }
 
-        if self.quad_vertices.len() + 4 > MAX_VERTICES as usize {
-            self.flush();
+    pub fn get_asset_server(&self) -> &AssetServer {
+        &self.asset_server
+    }
+
+    pub fn borrow_context(&self) -> &glow::Context {
+        &self.gl
+    }
+
+    pub fn set_viewport(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        self.resolution = glm::vec2(width as f32, height as f32);
+        unsafe {
+            self.gl.viewport(x as i32, y as i32, width as i32, height as i32);
         }
+    }
 
-        let mut texture_index = 0.0;
-        for (i, t) in self.textures.iter().enumerate() {
-            if texture.get_renderer_id().unwrap() == *t {
-                texture_index = i as f32;
-            }
+    pub fn draw_flat_color_quad(&mut self, position: Vec3, size: Vec3, color: Vec4) {
+        let white_texture = self.white_texture.get_renderer_id().unwrap();
+        self.push_command(position, glm::vec2(size.x, size.y), color, white_texture);
+    }
+
+    pub fn draw_quad(&mut self, position: Vec3, size: Vec2, texture: &mut Texture) {
+        texture.load_or_placeholder(&self.gl, &self.asset_server.get_vfs());
+        self.push_command(position, size, glm::vec4(1.0, 1.0, 1.0,   1.0), texture.get_renderer_id().unwrap());
+    }
+
+    /// Draws a texture owned by the asset server. Nothing is drawn while it is still loading,
+    /// check `AssetServer::get_load_state` to draw something else in the meantime.
+    pub fn draw_texture(&mut self, position: Vec3, size: Vec2, texture: &Handle<Texture>) {
+        let renderer_id = self.asset_server.get_texture(texture).and_then(|texture| texture.get_renderer_id());
+        if let Some(renderer_id) = renderer_id {
+            self.push_command(position, size, glm::vec4(1.0, 1.0, 1.0, 1.0), renderer_id);
         }
+    }
 
-        if texture_index == 0.0 {
-            self.textures.push(texture.get_renderer_id().unwrap());
-            texture_index = self.textures.len() as f32 - 1.0;
+    /// Draws what was rendered into a framebuffer, like a minimap.
+    pub fn draw_framebuffer(&mut self, position: Vec3, size: Vec2, framebuffer: &Framebuffer) {
+        let renderer_id = framebuffer.get_color_texture().get_renderer_id().unwrap();
+        self.push_command(position, size, glm::vec4(1.0, 1.0, 1.0, 1.0), renderer_id);
+    }
+
+    /// Draws a mesh owned by the asset server with depth testing, lit by the renderer's
+    /// light. Nothing is drawn while it is still loading.
+    pub fn draw_mesh(&mut self, mesh: &Handle<Mesh>, transform: &Mat4, color: Vec4) {
+        let white_texture = self.white_texture.get_renderer_id().unwrap();
+        self.push_mesh_command(mesh, transform, color, white_texture);
+    }
+
+    pub fn draw_textured_mesh(&mut self, mesh: &Handle<Mesh>, transform: &Mat4, texture: &Handle<Texture>) {
+        let renderer_id = self.asset_server.get_texture(texture).and_then(|texture| texture.get_renderer_id());
+        if let Some(renderer_id) = renderer_id {
+            self.push_mesh_command(mesh, transform, glm::vec4(1.0, 1.0, 1.0, 1.0), renderer_id);
         }
-        let color = glm::vec4(1.0, 1.0, 1.0,   1.0);
+    }
 
-        self.quad_vertices.push( QuadVertex {
-            position,
-            color,
-            texture_coordinate: glm::vec2(0.0, 0.0),
-            texture_index
-        });
+    /// Draws a quad one unit wide centered on the origin of `transform`, so sprites can be
+    /// rotated and scaled. The color tints the texture, a white quad is drawn without one.
+    pub fn draw_sprite(&mut self, transform: &Mat4, color: Vec4, texture: Option<&Handle<Texture>>) {
+        let renderer_id = match texture {
+            Some(texture) => match self.asset_server.get_texture(texture).and_then(|texture| texture.get_renderer_id()) {
+                Some(renderer_id) => renderer_id,
+                None => return
+            },
+            None => self.white_texture.get_renderer_id().unwrap()
+        };
+        let transform = glm::translate(transform, &glm::vec3(-0.5, -0.5, 0.0));
+        let position = glm::vec3(transform[(0, 3)], transform[(1, 3)], transform[(2, 3)]);
+        self.push_transformed_command(position, transform, color, renderer_id);
+    }
 
-        self.quad_vertices.push(QuadVertex {
-            position: glm::vec3(position.x + size.x, position.y, position.z),
+    fn push_mesh_command(&mut self, mesh: &Handle<Mesh>, transform: &Mat4, color: Vec4, texture: glow::Texture) {
+        if self.is_culled() {
+            return;
+        }
+        let color = self.premultiply(color);
+        self.mesh_commands.push(MeshCommand {
+            mesh: mesh.clone(),
+            transform: *transform,
             color,
-            texture_coordinate: glm::vec2(1.0, 0.0),
-            texture_index
+            texture
         });
+    }
 
-        self.quad_vertices.push(QuadVertex {
-            position: glm::vec3(position.x + size.x, position.y + size.y, position.z),
-            color,
-            texture_coordinate: glm::vec2(1.0, 1.0),
-            texture_index
-        });
+    fn premultiply(&self, color: Vec4) -> Vec4 {
+        if self.premultiplied_alpha {
+            glm::vec4(color.x * color.w, color.y * color.w, color.z * color.w, color.w)
+        } else {
+            color
+        }
+    }
+
+    fn push_command(&mut self, position: Vec3, size: Vec2, color: Vec4, texture: glow::Texture) {
+        let transform = glm::scale(&glm::translate(&glm::identity(), &position), &glm::vec3(size.x, size.y, 1.0));
+        self.push_transformed_command(position, transform, color, texture);
+    }
+
+    fn push_transformed_command(&mut self, position: Vec3, transform: Mat4, color: Vec4, texture: glow::Texture) {
+        if self.is_culled() {
+            return;
+        }
+        let color = self.premultiply(color);
 
-        self.quad_vertices.push(QuadVertex {
-            position: glm::vec3(position.x, position.y + size.y, position.z),
+        self.quad_commands.push(QuadCommand {
+            key: self.sort_key,
+            position,
+            transform,
             color,
-            texture_coordinate: glm::vec2(0.0, 1.0),
-            texture_index
+            texture,
+            batch_mode: self.batch_mode
         });
+    }
+
+    fn push_quad_vertices(&mut self, transform: &Mat4, color: Vec4, texture_index: f32) {
+        for corner in [glm::vec2(0.0, 0.0), glm::vec2(1.0, 0.0), glm::vec2(1.0, 1.0), glm::vec2(0.0, 1.0)] {
+            let position = transform * glm::vec4(corner.x, corner.y, 0.0, 1.0);
+            self.quad_vertices.push(QuadVertex {
+                position: glm::vec3(position.x, position.y, position.z),
+                color,
+                texture_coordinate: corner,
+                texture_index
+            });
+        }
 
         self.index_count += 6;
     }
@@ -0,0 +1,115 @@
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use glow::HasContext;
+
+/// A gl object waiting to be deleted. Wrappers such as `Texture` or `VertexBuffer` push their
+/// object here when dropped since they don't hold on to the context; the renderer deletes
+/// everything queued at the start of every frame.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum GlResource {
+    Buffer(glow::Buffer),
+    VertexArray(glow::VertexArray),
+    Program(glow::Program),
+    Texture(glow::Texture),
+    Framebuffer(glow::Framebuffer),
+    Renderbuffer(glow::Renderbuffer)
+}
+
+impl GlResource {
+    fn kind(&self) -> usize {
+        match self {
+            GlResource::Buffer(_) => 0,
+            GlResource::VertexArray(_) => 1,
+            GlResource::Program(_) => 2,
+            GlResource::Texture(_) => 3,
+            GlResource::Framebuffer(_) => 4,
+            GlResource::Renderbuffer(_) => 5
+        }
+    }
+}
+
+const KIND_NAMES: [&str; 6] = ["buffer", "vertex array", "program", "texture", "framebuffer", "renderbuffer"];
+
+static RELEASE_QUEUE: Mutex<Vec<GlResource>> = Mutex::new(Vec::new());
+
+static LIVE_RESOURCES: [AtomicIsize; 6] = [
+    AtomicIsize::new(0),
+    AtomicIsize::new(0),
+    AtomicIsize::new(0),
+    AtomicIsize::new(0),
+    AtomicIsize::new(0),
+    AtomicIsize::new(0)
+];
+
+/// Registers a freshly created gl object so it shows up in the leak report until released.
+pub(crate) fn track(resource: GlResource) {
+    LIVE_RESOURCES[resource.kind()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Queues a gl object for deletion. Safe to call from any thread, the deletion itself
+/// happens on the thread owning the context.
+pub(crate) fn release(resource: GlResource) {
+    LIVE_RESOURCES[resource.kind()].fetch_sub(1, Ordering::Relaxed);
+    RELEASE_QUEUE.lock().unwrap().push(resource);
+}
+
+/// Deletes every queued gl object.
+pub(crate) fn collect(gl: &glow::Context) {
+    let released: Vec<GlResource> = RELEASE_QUEUE.lock().unwrap().drain(..).collect();
+    unsafe {
+        for resource in released {
+            match resource {
+                GlResource::Buffer(buffer) => gl.delete_buffer(buffer),
+                GlResource::VertexArray(vertex_array) => gl.delete_vertex_array(vertex_array),
+                GlResource::Program(program) => gl.delete_program(program),
+                GlResource::Texture(texture) => gl.delete_texture(texture),
+                GlResource::Framebuffer(framebuffer) => gl.delete_framebuffer(framebuffer),
+                GlResource::Renderbuffer(renderbuffer) => gl.delete_renderbuffer(renderbuffer)
+            }
+        }
+    }
+}
+
+/// Prints the gl objects that were created but never dropped.
+pub(crate) fn report_leaks() {
+    for (kind, live) in LIVE_RESOURCES.iter().enumerate() {
+        let live = live.load(Ordering::Relaxed);
+        if live > 0 {
+            eprintln!("adze: leaked {} {} object(s)", live, KIND_NAMES[kind]);
+        }
+    }
+}
+
+/// Owns the gl context. Kept as the last field of the renderer so that it is dropped after
+/// every resource the renderer owns, deleting them with the context still alive.
+pub(crate) struct ContextGuard {
+    gl: glow::Context
+}
+
+impl ContextGuard {
+    pub(crate) fn new(gl: glow::Context) -> ContextGuard {
+        ContextGuard {
+            gl
+        }
+    }
+}
+
+impl Deref for ContextGuard {
+    type Target = glow::Context;
+
+    fn deref(&self) -> &glow::Context {
+        &self.gl
+    }
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        collect(&self.gl);
+
+        if cfg!(debug_assertions) {
+            report_leaks();
+        }
+    }
+}
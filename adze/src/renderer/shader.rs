@@ -1,8 +1,34 @@
+use std::fmt;
+use std::io;
+
 use glow::HasContext;
 use nalgebra_glm::Mat4;
 use crate::glm::{Vec3, Vec2, Vec4, Mat3};
+use crate::renderer::resource::{self, GlResource};
+use crate::vfs::Vfs;
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Read(String, io::Error),
+    MissingStage(&'static str),
+    Compile(String),
+    Link(String)
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Read(path, err) => write!(f, "Could not read shader {}: {}", path, err),
+            ShaderError::MissingStage(stage) => write!(f, "Shader source has no {} stage", stage),
+            ShaderError::Compile(log) => write!(f, "Could not compile shader: {}", log),
+            ShaderError::Link(log) => write!(f, "Could not link shader: {}", log)
+        }
+    }
+}
 
-fn compile_shader(gl: &glow::Context, src: &str, ty: u32) -> glow::Shader {
+impl std::error::Error for ShaderError {}
+
+fn compile_shader(gl: &glow::Context, src: &str, ty: u32) -> Result<glow::Shader, ShaderError> {
     unsafe {
         let shader = gl.create_shader(ty).unwrap();
         gl.shader_source(shader, src);
@@ -11,15 +37,16 @@ fn compile_shader(gl: &glow::Context, src: &str, ty: u32) -> glow::Shader {
         // Get the compile status
         let status = gl.get_shader_compile_status(shader);
 
-        // Fail on error
         if !status {
-            panic!("{}", gl.get_shader_info_log(shader));
+            let log = gl.get_shader_info_log(shader);
+            gl.delete_shader(shader);
+            return Err(ShaderError::Compile(log));
         }
-        shader
+        Ok(shader)
     }
 }
 
-fn link_program(gl: &glow::Context, vs: glow::Shader, fs: glow::Shader) -> glow::Program {
+fn link_program(gl: &glow::Context, vs: glow::Shader, fs: glow::Shader) -> Result<glow::Program, ShaderError> {
     unsafe {
         let program = gl.create_program().unwrap();
         gl.attach_shader(program, vs);
@@ -28,12 +55,53 @@ fn link_program(gl: &glow::Context, vs: glow::Shader, fs: glow::Shader) -> glow:
         // Get the link status
         let status = gl.get_program_link_status(program);
 
-        // Fail on error
+        // the program keeps its own copy of the compiled stages
+        gl.detach_shader(program, vs);
+        gl.detach_shader(program, fs);
+        gl.delete_shader(vs);
+        gl.delete_shader(fs);
+
         if !status {
-            panic!("{}", gl.get_program_info_log(program));
+            let log = gl.get_program_info_log(program);
+            gl.delete_program(program);
+            return Err(ShaderError::Link(log));
+        }
+
+        resource::track(GlResource::Program(program));
+        Ok(program)
+    }
+}
+
+/// Splits a single file shader into its vertex and fragment sources. Each stage starts with a
+/// `#type vertex` or `#type fragment` line.
+pub fn split_stages(source: &str) -> Result<(String, String), ShaderError> {
+    let mut vertex = None;
+    let mut fragment = None;
+    let mut current: Option<&mut Option<String>> = None;
+
+    for line in source.lines() {
+        if let Some(stage) = line.trim().strip_prefix("#type") {
+            current = match stage.trim() {
+                "vertex" => Some(&mut vertex),
+                "fragment" | "pixel" => Some(&mut fragment),
+                _ => None
+            };
+            if let Some(stage) = current.as_mut() {
+                **stage = Some(String::new());
+            }
+            continue;
+        }
+
+        if let Some(Some(stage)) = current.as_mut() {
+            stage.push_str(line);
+            stage.push('\n');
         }
-        program
     }
+
+    Ok((
+        vertex.ok_or(ShaderError::MissingStage("vertex"))?,
+        fragment.ok_or(ShaderError::MissingStage("fragment"))?
+    ))
 }
 
 pub struct Shader {
@@ -42,12 +110,34 @@ pub struct Shader {
 
 impl Shader {
     pub fn new(gl: &glow::Context, vertex_src: &str, fragment_src: &str) -> Shader {
-        let vs = compile_shader(gl, vertex_src, glow::VERTEX_SHADER);
-        let fs = compile_shader(gl, fragment_src, glow::FRAGMENT_SHADER);
-        let renderer_id = link_program(gl, vs, fs);
-        Shader {
+        Shader::try_new(gl, vertex_src, fragment_src).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_new(gl: &glow::Context, vertex_src: &str, fragment_src: &str) -> Result<Shader, ShaderError> {
+        let vs = compile_shader(gl, vertex_src, glow::VERTEX_SHADER)?;
+        let fs = match compile_shader(gl, fragment_src, glow::FRAGMENT_SHADER) {
+            Ok(fs) => fs,
+            Err(err) => {
+                unsafe { gl.delete_shader(vs); }
+                return Err(err);
+            }
+        };
+        let renderer_id = link_program(gl, vs, fs)?;
+        Ok(Shader {
             renderer_id
-        }
+        })
+    }
+
+    /// Reads and builds a single file shader, see `split_stages`.
+    pub fn load(gl: &glow::Context, vfs: &Vfs, path: &str) -> Result<Shader, ShaderError> {
+        let source = vfs.read_to_string(path).map_err(|err| ShaderError::Read(path.to_string(), err))?;
+        Shader::from_source(gl, &source)
+    }
+
+    /// Builds a shader from a single source split with `split_stages`.
+    pub fn from_source(gl: &glow::Context, source: &str) -> Result<Shader, ShaderError> {
+        let (vertex_src, fragment_src) = split_stages(source)?;
+        Shader::try_new(gl, &vertex_src, &fragment_src)
     }
 
     pub fn upload_uniform_integer1(&self, gl: &glow::Context, name: &str, value: i32) {
@@ -106,6 +196,16 @@ impl Shader {
         }
     }
 
+    /// Does nothing when the shader has no block called `name`, which includes blocks the
+    /// compiler dropped because the shader never reads them.
+    pub fn bind_uniform_block(&self, gl: &glow::Context, name: &str, binding: u32) {
+        unsafe {
+            if let Some(index) = gl.get_uniform_block_index(self.renderer_id, name) {
+                gl.uniform_block_binding(self.renderer_id, index, binding);
+            }
+        }
+    }
+
     pub fn bind(&self, gl: &glow::Context) {
         unsafe {
             gl.use_program(Some(self.renderer_id));
@@ -117,4 +217,10 @@ impl Shader {
             gl.use_program(None);
         }
     }
-}
\ No newline at end of file
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        resource::release(GlResource::Program(self.renderer_id));
+    }
+}
@@ -0,0 +1,125 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::glm::{Mat4, Vec3, Vec4};
+use crate::renderer::BatchMode;
+
+/// Where a quad goes in the draw order. Quads are drawn by layer, then by order in layer and
+/// then, when `y_sort` is set, from the top of the screen down so lower sprites overlap the
+/// ones behind them, after the quads of the same order that aren't y sorted. Quads that still
+/// tie are grouped by `material` so quads sharing a
+/// texture or shader can be given the same one to end up in the same batch, quads with equal
+/// keys keep the order they were drawn in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SortKey {
+    pub layer: i32,
+    pub order_in_layer: i32,
+    pub y_sort: bool,
+    pub material: u32
+}
+
+impl SortKey {
+    pub fn new(layer: i32, order_in_layer: i32) -> SortKey {
+        SortKey {
+            layer,
+            order_in_layer,
+            y_sort: false,
+            material: 0
+        }
+    }
+
+    pub fn with_y_sort(mut self) -> SortKey {
+        self.y_sort = true;
+        self
+    }
+
+    pub fn with_material(mut self, material: u32) -> SortKey {
+        self.material = material;
+        self
+    }
+}
+
+/// A quad submitted between `Renderer::begin` and `Renderer::end`, kept until the end of the
+/// scene so it can be sorted before being batched.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct QuadCommand {
+    pub(crate) key: SortKey,
+    // where the quad is sorted from
+    pub(crate) position: Vec3,
+    // places the corners of a quad from (0, 0) to (1, 1)
+    pub(crate) transform: Mat4,
+    pub(crate) color: Vec4,
+    pub(crate) texture: glow::Texture,
+    // the mode set when the quad was drawn, it's only batched at the end of the scene
+    pub(crate) batch_mode: BatchMode
+}
+
+fn draw_order(key: &SortKey, position: &Vec3, other_key: &SortKey, other_position: &Vec3) -> Ordering {
+    key.layer.cmp(&other_key.layer)
+        .then(key.order_in_layer.cmp(&other_key.order_in_layer))
+        // comparing y only when both are y sorted would make the order intransitive
+        .then(key.y_sort.cmp(&other_key.y_sort))
+        .then_with(|| if key.y_sort {
+            other_position.y.total_cmp(&position.y)
+        } else {
+            Ordering::Equal
+        })
+        // back to front, the camera looks down -z
+        .then(position.z.total_cmp(&other_position.z))
+        .then(key.material.cmp(&other_key.material))
+}
+
+/// Orders quads back to front. Blending needs everything behind a translucent quad drawn
+/// before it, so the renderer draws sprites in this order without writing depth. The sort is
+/// stable, ties keep their submission order.
+pub(crate) fn sort_quads(quads: &mut [QuadCommand]) {
+    quads.sort_by(|a, b| draw_order(&a.key, &a.position, &b.key, &b.position));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::glm;
+    use crate::renderer::sorting::{draw_order, SortKey};
+
+    #[test]
+    fn layers_come_before_y_sorting_and_depth() {
+        let back = SortKey::new(0, 5).with_y_sort();
+        let front = SortKey::new(1, 0).with_y_sort();
+
+        let low = glm::vec3(0.0, -10.0, 0.5);
+        let high = glm::vec3(0.0, 10.0, 0.0);
+        assert_eq!(draw_order(&back, &low, &front, &high), Ordering::Less);
+
+        // within a layer the sprite further down the screen is drawn last
+        assert_eq!(draw_order(&front, &low, &front, &high), Ordering::Greater);
+        assert_eq!(draw_order(&SortKey::default(), &glm::vec3(0.0, 0.0, 0.2), &SortKey::default(), &glm::vec3(0.0, 0.0, 0.1)), Ordering::Greater);
+    }
+
+    #[test]
+    fn y_sorted_and_plain_quads_in_one_layer_have_a_total_order() {
+        let quads = [
+            (SortKey::default().with_y_sort(), glm::vec3(0.0, 1.0, 0.0)),
+            (SortKey::default(), glm::vec3(0.0, 0.0, 0.5)),
+            (SortKey::default().with_y_sort(), glm::vec3(0.0, 2.0, 1.0)),
+            (SortKey::default(), glm::vec3(0.0, 3.0, 0.0))
+        ];
+        let order = |a: usize, b: usize| draw_order(&quads[a].0, &quads[a].1, &quads[b].0, &quads[b].1);
+        for a in 0..quads.len() {
+            for b in 0..quads.len() {
+                assert_eq!(order(a, b), order(b, a).reverse());
+                for c in 0..quads.len() {
+                    if order(a, b) == Ordering::Less && order(b, c) == Ordering::Less {
+                        assert_eq!(order(a, c), Ordering::Less);
+                    }
+                }
+            }
+        }
+
+        let mut sorted: Vec<usize> = (0..quads.len()).collect();
+        sorted.sort_by(|&a, &b| order(a, b));
+        assert_eq!(sorted, vec![3, 1, 2, 0]);
+    }
+}
@@ -1,5 +1,293 @@
-use image::{GenericImageView, DynamicImage};
+use std::fmt;
+use std::path::Path;
+
+use image::{GenericImageView, DynamicImage, ImageError, ImageFormat};
+use image::codecs::hdr::HdrDecoder;
 use glow::{HasContext, PixelUnpackData};
+use crate::glm::{self, Vec4};
+use crate::renderer::resource::{self, GlResource};
+use crate::vfs::Vfs;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder
+}
+
+fn to_opengl_wrap(wrap: TextureWrap) -> i32 {
+    (match wrap {
+        TextureWrap::Repeat => glow::REPEAT,
+        TextureWrap::MirroredRepeat => glow::MIRRORED_REPEAT,
+        TextureWrap::ClampToEdge => glow::CLAMP_TO_EDGE,
+        TextureWrap::ClampToBorder => glow::CLAMP_TO_BORDER
+    }) as i32
+}
+
+/// How a texture is sampled. The default matches what textures always used: linear
+/// minification, nearest magnification, repeating and no mipmaps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerDesc {
+    pub min_filter: TextureFilter,
+    pub mag_filter: TextureFilter,
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    /// Maximum anisotropy, 1.0 turns it off. Clamped to what the driver supports and ignored
+    /// without the anisotropic filtering extension.
+    pub anisotropy: f32,
+    /// Allocates and generates the full mip chain when the texture is created. Mip levels are
+    /// picked with the min filter.
+    pub mipmaps: bool,
+    /// Color read outside the texture with `TextureWrap::ClampToBorder`.
+    pub border_color: Vec4
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc {
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Nearest,
+            wrap_s: TextureWrap::Repeat,
+            wrap_t: TextureWrap::Repeat,
+            anisotropy: 1.0,
+            mipmaps: false,
+            border_color: glm::vec4(0.0, 0.0, 0.0, 0.0)
+        }
+    }
+}
+
+impl SamplerDesc {
+    /// Nearest filtering everywhere, for pixel art.
+    pub fn nearest() -> SamplerDesc {
+        SamplerDesc {
+            min_filter: TextureFilter::Nearest,
+            mag_filter: TextureFilter::Nearest,
+            ..SamplerDesc::default()
+        }
+    }
+
+    /// Linear filtering everywhere.
+    pub fn linear() -> SamplerDesc {
+        SamplerDesc {
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Linear,
+            ..SamplerDesc::default()
+        }
+    }
+
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> SamplerDesc {
+        self.wrap_s = wrap;
+        self.wrap_t = wrap;
+        self
+    }
+
+    pub fn with_mipmaps(mut self) -> SamplerDesc {
+        self.mipmaps = true;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> SamplerDesc {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn with_border_color(mut self, border_color: Vec4) -> SamplerDesc {
+        self.border_color = border_color;
+        self
+    }
+
+    fn get_min_filter(&self) -> i32 {
+        (match (self.min_filter, self.mipmaps) {
+            (TextureFilter::Nearest, false) => glow::NEAREST,
+            (TextureFilter::Linear, false) => glow::LINEAR,
+            (TextureFilter::Nearest, true) => glow::NEAREST_MIPMAP_NEAREST,
+            (TextureFilter::Linear, true) => glow::LINEAR_MIPMAP_LINEAR
+        }) as i32
+    }
+
+    fn get_mag_filter(&self) -> i32 {
+        (match self.mag_filter {
+            TextureFilter::Nearest => glow::NEAREST,
+            TextureFilter::Linear => glow::LINEAR
+        }) as i32
+    }
+
+    fn get_levels(&self, width: u32, height: u32) -> i32 {
+        if self.mipmaps {
+            32 - width.max(height).max(1).leading_zeros() as i32
+        } else {
+            1
+        }
+    }
+
+    /// Applies the sampler to the texture bound to `TEXTURE_2D`.
+    fn apply(&self, gl: &glow::Context) {
+        unsafe {
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, self.get_min_filter());
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, self.get_mag_filter());
+
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, to_opengl_wrap(self.wrap_s));
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, to_opengl_wrap(self.wrap_t));
+
+            gl.tex_parameter_f32_slice(glow::TEXTURE_2D, glow::TEXTURE_BORDER_COLOR, self.border_color.as_slice());
+
+            if gl.supported_extensions().contains("GL_EXT_texture_filter_anisotropic") {
+                let max_anisotropy = gl.get_parameter_f32(glow::MAX_TEXTURE_MAX_ANISOTROPY_EXT);
+                gl.tex_parameter_f32(glow::TEXTURE_2D, glow::TEXTURE_MAX_ANISOTROPY_EXT, self.anisotropy.max(1.0).min(max_anisotropy));
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    /// The file couldn't be read or decoded.
+    Image(String, ImageError)
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Image(path, err) => write!(f, "Could not load image {}: {}", path, err)
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Image(_, err) => Some(err)
+        }
+    }
+}
+
+/// Decoded pixels ready to be uploaded, along with the gl formats describing them.
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    pub internal_format: u32,
+    pub data_format: u32,
+    pub data_type: u32,
+    /// Channel mapping for formats with less than four channels, grayscale images are read
+    /// back as gray instead of red.
+    pub swizzle: Option<[i32; 4]>
+}
+
+const GRAY_SWIZZLE: [i32; 4] = [glow::RED as i32, glow::RED as i32, glow::RED as i32, glow::ONE as i32];
+const GRAY_ALPHA_SWIZZLE: [i32; 4] = [glow::RED as i32, glow::RED as i32, glow::RED as i32, glow::GREEN as i32];
+
+fn premultiply_u8(data: &mut [u8], channels: usize) {
+    for pixel in data.chunks_exact_mut(channels) {
+        let alpha = pixel[channels - 1] as u32;
+        for channel in pixel[..channels - 1].iter_mut() {
+            *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+fn premultiply_u16(data: &mut [u16], channels: usize) {
+    for pixel in data.chunks_exact_mut(channels) {
+        let alpha = pixel[channels - 1] as u32;
+        for channel in pixel[..channels - 1].iter_mut() {
+            *channel = ((*channel as u32 * alpha + 32767) / 65535) as u16;
+        }
+    }
+}
+
+impl ImageData {
+    /// Reads and decodes an image, keeping its channel count and bit depth. Radiance `.hdr`
+    /// files are loaded as 32 bit floats. With `premultiply_alpha` the color channels of images
+    /// with an alpha channel are multiplied by it.
+    pub fn load(vfs: &Vfs, path: &str, premultiply_alpha: bool) -> Result<ImageData, TextureError> {
+        let bytes = vfs.read(path).map_err(|err| TextureError::Image(path.to_string(), ImageError::IoError(err)))?;
+        ImageData::from_bytes(path, &bytes, premultiply_alpha)
+    }
+
+    /// Decodes an image that has already been read, `path` is only used to pick the format
+    /// from its extension and to report errors.
+    pub fn from_bytes(path: &str, bytes: &[u8], premultiply_alpha: bool) -> Result<ImageData, TextureError> {
+        let is_hdr = Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+        if is_hdr {
+            return ImageData::load_hdr(bytes).map_err(|err| TextureError::Image(path.to_string(), err));
+        }
+
+        // formats like tga can't be told apart by their contents
+        let img = match ImageFormat::from_path(path) {
+            Ok(format) => image::load_from_memory_with_format(bytes, format),
+            Err(_) => image::load_from_memory(bytes)
+        };
+        let img = img.map_err(|err| TextureError::Image(path.to_string(), err))?;
+        Ok(ImageData::from_image(img, premultiply_alpha))
+    }
+
+    fn load_hdr(bytes: &[u8]) -> Result<ImageData, ImageError> {
+        let decoder = HdrDecoder::new(bytes)?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr()?;
+
+        let data = pixels.iter()
+            .flat_map(|pixel| pixel.0.iter().flat_map(|channel| channel.to_ne_bytes()))
+            .collect();
+
+        Ok(ImageData {
+            width: metadata.width,
+            height: metadata.height,
+            data,
+            internal_format: glow::RGB32F,
+            data_format: glow::RGB,
+            data_type: glow::FLOAT,
+            swizzle: None
+        })
+    }
+
+    pub fn from_image(img: DynamicImage, premultiply_alpha: bool) -> ImageData {
+        let (width, height) = img.dimensions();
+
+        let eight_bit = |mut data: Vec<u8>, channels: usize, internal_format, data_format, swizzle| {
+            if premultiply_alpha && (channels == 2 || channels == 4) {
+                premultiply_u8(&mut data, channels);
+            }
+            (data, internal_format, data_format, glow::UNSIGNED_BYTE, swizzle)
+        };
+        let sixteen_bit = |mut data: Vec<u16>, channels: usize, internal_format, data_format, swizzle| {
+            if premultiply_alpha && (channels == 2 || channels == 4) {
+                premultiply_u16(&mut data, channels);
+            }
+            (bytemuck::cast_slice(data.as_slice()).to_vec(), internal_format, data_format, glow::UNSIGNED_SHORT, swizzle)
+        };
+
+        let (data, internal_format, data_format, data_type, swizzle) = match img {
+            DynamicImage::ImageLuma8(img) => eight_bit(img.into_raw(), 1, glow::R8, glow::RED, Some(GRAY_SWIZZLE)),
+            DynamicImage::ImageLumaA8(img) => eight_bit(img.into_raw(), 2, glow::RG8, glow::RG, Some(GRAY_ALPHA_SWIZZLE)),
+            DynamicImage::ImageRgb8(img) => eight_bit(img.into_raw(), 3, glow::RGB8, glow::RGB, None),
+            DynamicImage::ImageRgba8(img) => eight_bit(img.into_raw(), 4, glow::RGBA8, glow::RGBA, None),
+            DynamicImage::ImageBgr8(img) => eight_bit(img.into_raw(), 3, glow::RGB8, glow::BGR, None),
+            DynamicImage::ImageBgra8(img) => eight_bit(img.into_raw(), 4, glow::RGBA8, glow::BGRA, None),
+            DynamicImage::ImageLuma16(img) => sixteen_bit(img.into_raw(), 1, glow::R16, glow::RED, Some(GRAY_SWIZZLE)),
+            DynamicImage::ImageLumaA16(img) => sixteen_bit(img.into_raw(), 2, glow::RG16, glow::RG, Some(GRAY_ALPHA_SWIZZLE)),
+            DynamicImage::ImageRgb16(img) => sixteen_bit(img.into_raw(), 3, glow::RGB16, glow::RGB, None),
+            DynamicImage::ImageRgba16(img) => sixteen_bit(img.into_raw(), 4, glow::RGBA16, glow::RGBA, None)
+        };
+
+        ImageData {
+            width,
+            height,
+            data,
+            internal_format,
+            data_format,
+            data_type,
+            swizzle
+        }
+    }
+}
 
 pub struct Texture {
     renderer_id: Option<glow::Texture>,
@@ -9,11 +297,26 @@ pub struct Texture {
     height: u32,
     data: Vec<u8>,
     internal_format: u32,
-    data_format: u32
+    data_format: u32,
+    data_type: u32,
+    premultiply_alpha: bool,
+    sampler: SamplerDesc
+}
+
+/// Creates a texture with storage for `width` by `height` texels and every mip level the
+/// sampler asks for.
+unsafe fn create_texture(gl: &glow::Context, internal_format: u32, width: u32, height: u32, sampler: &SamplerDesc) -> glow::Texture {
+    let renderer_id = gl.create_texture().unwrap();
+    resource::track(GlResource::Texture(renderer_id));
+
+    gl.bind_texture(glow::TEXTURE_2D, Some(renderer_id));
+    gl.tex_storage_2d(glow::TEXTURE_2D, sampler.get_levels(width, height), internal_format, width as i32, height as i32);
+    sampler.apply(gl);
+    renderer_id
 }
 
 impl Texture {
-    pub fn new(path: String, tiling: f32) -> Texture {
+    pub fn new(path: String, tiling: f32, sampler: SamplerDesc) -> Texture {
         Texture {
             renderer_id: None,
             path,
@@ -22,11 +325,19 @@ impl Texture {
             height: 0,
             data: vec![],
             internal_format: 0,
-            data_format: 0
+            data_format: 0,
+            data_type: glow::UNSIGNED_BYTE,
+            premultiply_alpha: false,
+            sampler
         }
     }
 
+    /// Hands ownership of `renderer_id` to the texture, releasing the one it held before.
     pub fn set_renderer_id(&mut self, renderer_id: glow::Texture) {
+        if let Some(previous) = self.renderer_id {
+            resource::release(GlResource::Texture(previous));
+        }
+        resource::track(GlResource::Texture(renderer_id));
         self.renderer_id = Some(renderer_id);
     }
 
@@ -50,52 +361,113 @@ impl Texture {
         self.tiling
     }
 
+    /// Loads the image at the texture's path and uploads it. Called lazily by
+    /// `Renderer::draw_quad`, textures loaded through the `AssetServer` are decoded on worker
+    /// threads instead.
+    pub fn load(&mut self, gl: &glow::Context, vfs: &Vfs) -> Result<(), TextureError> {
+        let image = ImageData::load(vfs, self.get_path(), self.premultiply_alpha)?;
+        self.upload(gl, image);
+        Ok(())
+    }
+
     // https://www.reddit.com/r/rust/comments/7me7zr/using_image_crate_to_load_an_image_and_use_it_as/
-    pub(crate) fn init(&mut self, gl: &glow::Context) {
-        match image::open(String::from(self.get_path())) {
-            Err(err) => panic!("Could not load image {}: {}", self.get_path(), err),
-            Ok(img) => unsafe {
-                let (width, height) = img.dimensions();
+    /// Replaces the texture's storage with `image`.
+    pub fn upload(&mut self, gl: &glow::Context, image: ImageData) {
+        unsafe {
+            let renderer_id = create_texture(gl, image.internal_format, image.width, image.height, &self.sampler);
 
-                let (image, internal_format, data_format) = match img {
-                    DynamicImage::ImageRgb8(img) => (img.into_raw(), glow::RGB8, glow::RGB),
-                    DynamicImage::ImageRgba8(img) => (img.into_raw(), glow::RGBA8, glow::RGBA),
-                    img => (img.to_rgb8().into_raw(), glow::RGB8, glow::RGB)
-                };
+            if let Some(swizzle) = image.swizzle {
+                gl.tex_parameter_i32_slice(glow::TEXTURE_2D, glow::TEXTURE_SWIZZLE_RGBA, &swizzle);
+            }
 
-                let renderer_id = gl.create_texture().unwrap();
-                gl.bind_texture(glow::TEXTURE_2D, Some(renderer_id));
-                gl.tex_storage_2d(glow::TEXTURE_2D, 1, internal_format, width as i32, height as i32);
+            // rows of rgb and single channel images aren't 4 byte aligned
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0, image.width as i32, image.height as i32, image.data_format, image.data_type, PixelUnpackData::Slice(image.data.as_slice()));
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
 
-                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
-                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+            if self.sampler.mipmaps {
+                gl.generate_mipmap(glow::TEXTURE_2D);
+            }
 
-                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::REPEAT as i32);
-                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::REPEAT as i32);
+            if let Some(previous) = self.renderer_id.replace(renderer_id) {
+                resource::release(GlResource::Texture(previous));
+            }
+        }
+
+        self.width = image.width;
+        self.height = image.height;
+        self.internal_format = image.internal_format;
+        self.data_format = image.data_format;
+        self.data_type = image.data_type;
+    }
 
-                gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0, width as i32, height as i32, data_format, glow::UNSIGNED_BYTE, PixelUnpackData::Slice(image.as_slice()));
+    /// Replaces the texture's pixels with `image`. When the size and format match the current
+    /// storage is written to in place, so the `glow::Texture` stays the same and anything that
+    /// held on to it sees the new image, otherwise the texture is uploaded again.
+    pub fn reload(&mut self, gl: &glow::Context, image: ImageData) {
+        let renderer_id = match self.renderer_id {
+            Some(renderer_id) if image.width == self.width && image.height == self.height && image.internal_format == self.internal_format => renderer_id,
+            _ => return self.upload(gl, image)
+        };
 
-                self.set_renderer_id(renderer_id);
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(renderer_id));
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0, image.width as i32, image.height as i32, image.data_format, image.data_type, PixelUnpackData::Slice(image.data.as_slice()));
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+
+            if self.sampler.mipmaps {
+                gl.generate_mipmap(glow::TEXTURE_2D);
             }
         }
+
+        self.data_format = image.data_format;
+        self.data_type = image.data_type;
     }
 
-    pub fn from_dimensions(gl: &glow::Context, width: u32, height: u32) -> Self {
-        unsafe {
-            let internal_format = glow::RGBA8;
-            let data_format = glow::RGBA;
+    /// Loads the texture if it hasn't been yet, reporting the error and falling back to the
+    /// placeholder if the image can't be read.
+    pub(crate) fn load_or_placeholder(&mut self, gl: &glow::Context, vfs: &Vfs) {
+        if self.renderer_id.is_some() {
+            return;
+        }
+
+        if let Err(err) = self.load(gl, vfs) {
+            eprintln!("{}", err);
+            self.load_placeholder(gl);
+        }
+    }
 
-            let renderer_id = gl.create_texture().unwrap();
+    /// Turns the texture into a 1x1 magenta texture so missing images stand out without
+    /// being loaded again every frame.
+    pub(crate) fn load_placeholder(&mut self, gl: &glow::Context) {
+        self.upload(gl, ImageData {
+            width: 1,
+            height: 1,
+            data: vec![255, 0, 255, 255],
+            internal_format: glow::RGBA8,
+            data_format: glow::RGBA,
+            data_type: glow::UNSIGNED_BYTE,
+            swizzle: None
+        });
+    }
 
-            gl.bind_texture(glow::TEXTURE_2D, Some(renderer_id));
+    /// Multiplies color by alpha when the image is loaded, for use with a renderer created with
+    /// `RendererSettings::premultiplied_alpha`.
+    pub fn set_premultiply_alpha(&mut self, premultiply_alpha: bool) {
+        self.premultiply_alpha = premultiply_alpha;
+    }
 
-            gl.tex_storage_2d(glow::TEXTURE_2D, 1, internal_format, width as i32, height as i32);
+    pub fn get_premultiply_alpha(&self) -> bool {
+        self.premultiply_alpha
+    }
 
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+    pub fn from_dimensions(gl: &glow::Context, width: u32, height: u32, sampler: SamplerDesc) -> Self {
+        unsafe {
+            let internal_format = glow::RGBA8;
+            let data_format = glow::RGBA;
 
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::REPEAT as i32);
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::REPEAT as i32);
+            let renderer_id = create_texture(gl, internal_format, width, height, &sampler);
 
             Texture {
                 renderer_id: Some(renderer_id),
@@ -105,26 +477,23 @@ impl Texture {
                 height,
                 data: vec![],
                 internal_format,
-                data_format
+                data_format,
+                data_type: glow::UNSIGNED_BYTE,
+                premultiply_alpha: false,
+                sampler
             }
         }
     }
 
-    pub fn from_data(gl: &glow::Context, data: Vec<u8>, width: u32, height: u32, internal_format: u32, data_format: u32) -> Self {
+    pub fn from_data(gl: &glow::Context, data: Vec<u8>, width: u32, height: u32, internal_format: u32, data_format: u32, sampler: SamplerDesc) -> Self {
         unsafe {
-            let renderer_id = gl.create_texture().unwrap();
-
-            gl.bind_texture(glow::TEXTURE_2D, Some(renderer_id));
-
-            gl.tex_storage_2d(glow::TEXTURE_2D, 1, internal_format, width as i32, height as i32);
-
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
-
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::REPEAT as i32);
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::REPEAT as i32);
+            let renderer_id = create_texture(gl, internal_format, width, height, &sampler);
 
             gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0, width as i32, height as i32, data_format, glow::UNSIGNED_BYTE, PixelUnpackData::Slice(data.as_slice()));
+            if sampler.mipmaps {
+                gl.generate_mipmap(glow::TEXTURE_2D);
+            }
+
             Texture {
                 renderer_id: Some(renderer_id),
                 path: "".to_string(),
@@ -133,7 +502,10 @@ impl Texture {
                 height,
                 data,
                 internal_format,
-                data_format
+                data_format,
+                data_type: glow::UNSIGNED_BYTE,
+                premultiply_alpha: false,
+                sampler
             }
         }
     }
@@ -142,10 +514,33 @@ impl Texture {
         self.data = data;
         Texture::bind(gl, self.renderer_id.unwrap(), 0);
         unsafe {
-            gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0, self.width as i32, self.height as i32, self.data_format, glow::UNSIGNED_BYTE, PixelUnpackData::Slice(self.data.as_slice()));
+            gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0, self.width as i32, self.height as i32, self.data_format, self.data_type, PixelUnpackData::Slice(self.data.as_slice()));
+            if self.sampler.mipmaps {
+                gl.generate_mipmap(glow::TEXTURE_2D);
+            }
         }
     }
 
+    /// Changes how the texture is sampled. Textures that aren't loaded yet pick it up when they
+    /// are. Mip levels are allocated on creation, so turning mipmaps on for a texture created
+    /// without them only regenerates its single level.
+    pub fn set_sampler(&mut self, gl: &glow::Context, sampler: SamplerDesc) {
+        self.sampler = sampler;
+        if let Some(renderer_id) = self.renderer_id {
+            Texture::bind(gl, renderer_id, 0);
+            sampler.apply(gl);
+            if sampler.mipmaps {
+                unsafe {
+                    gl.generate_mipmap(glow::TEXTURE_2D);
+                }
+            }
+        }
+    }
+
+    pub fn get_sampler(&self) -> SamplerDesc {
+        self.sampler
+    }
+
     pub fn get_data(&self) -> &Vec<u8> {
         &self.data
     }
@@ -178,6 +573,10 @@ impl Texture {
         self.data_format
     }
 
+    pub fn get_data_type(&self) -> u32 {
+        self.data_type
+    }
+
     pub fn set_data_format(&mut self, data_format: u32) {
         self.data_format = data_format;
     }
@@ -197,3 +596,69 @@ impl Texture {
     }
 }
 
+impl Drop for Texture {
+    fn drop(&mut self) {
+        if let Some(renderer_id) = self.renderer_id {
+            resource::release(GlResource::Texture(renderer_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageBuffer, ImageOutputFormat, LumaA, Rgb, Rgba};
+    use image::codecs::hdr::HdrEncoder;
+
+    use crate::renderer::texture::{ImageData, TextureError, GRAY_ALPHA_SWIZZLE, GRAY_SWIZZLE};
+
+    fn png(img: DynamicImage) -> Vec<u8> {
+        let mut bytes = vec![];
+        img.write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn eight_bit_grays_are_swizzled_and_premultiplied() {
+        let gray = png(DynamicImage::new_luma8(2, 1));
+        let image = ImageData::from_bytes("gray.png", &gray, true).unwrap();
+        assert_eq!((image.width, image.height, image.internal_format, image.data_format), (2, 1, glow::R8, glow::RED));
+        assert_eq!(image.swizzle, Some(GRAY_SWIZZLE));
+
+        let gray_alpha = png(DynamicImage::ImageLumaA8(ImageBuffer::<LumaA<u8>, _>::from_raw(3, 1, vec![255, 128, 1, 128, 200, 255]).unwrap()));
+        let image = ImageData::from_bytes("gray_alpha.png", &gray_alpha, true).unwrap();
+        assert_eq!((image.internal_format, image.data_format, image.data_type), (glow::RG8, glow::RG, glow::UNSIGNED_BYTE));
+        assert_eq!(image.swizzle, Some(GRAY_ALPHA_SWIZZLE));
+        // rounded to the nearest value, an opaque pixel is left alone
+        assert_eq!(image.data, vec![128, 128, 1, 128, 200, 255]);
+        assert_eq!(ImageData::from_bytes("gray_alpha.png", &gray_alpha, false).unwrap().data, vec![255, 128, 1, 128, 200, 255]);
+    }
+
+    #[test]
+    fn sixteen_bit_and_hdr_images_keep_their_precision() {
+        // the png encoder writes samples in native order where png wants big endian
+        let samples = [65535_u16, 1000, 0, 32768].iter().map(|sample| u16::from_ne_bytes(sample.to_be_bytes())).collect();
+        let rgba = ImageBuffer::<Rgba<u16>, _>::from_raw(1, 1, samples).unwrap();
+        let image = ImageData::from_bytes("deep.png", &png(DynamicImage::ImageRgba16(rgba)), true).unwrap();
+        assert_eq!((image.internal_format, image.data_format, image.data_type), (glow::RGBA16, glow::RGBA, glow::UNSIGNED_SHORT));
+        assert_eq!(image.swizzle, None);
+        let channels: Vec<u16> = image.data.chunks_exact(2).map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]])).collect();
+        assert_eq!(channels, vec![32768, 500, 0, 32768]);
+
+        let mut hdr = vec![];
+        HdrEncoder::new(&mut hdr).encode(&[Rgb([4.0, 0.5, 0.0]), Rgb([1.0, 1.0, 1.0])], 2, 1).unwrap();
+        let image = ImageData::from_bytes("sky.HDR", &hdr, true).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!((image.internal_format, image.data_format, image.data_type), (glow::RGB32F, glow::RGB, glow::FLOAT));
+        let channels: Vec<f32> = image.data.chunks_exact(4).map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect();
+        assert_eq!(channels, vec![4.0, 0.5, 0.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn garbage_is_reported_with_its_path() {
+        for path in ["broken.png", "broken.hdr", "broken"] {
+            let error = ImageData::from_bytes(path, b"not an image", false).err().unwrap();
+            assert!(matches!(&error, TextureError::Image(error_path, _) if error_path == path));
+            assert!(error.to_string().contains(path));
+        }
+    }
+}
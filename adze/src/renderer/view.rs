@@ -0,0 +1,125 @@
+use crate::glm::{self, Vec2, Vec4};
+use crate::renderer::camera::Viewport;
+
+/// A set of render layers, one per bit. Draws are tagged with `Renderer::set_render_layers`
+/// and a view only draws the ones its culling mask shares a bit with.
+pub type LayerMask = u32;
+
+/// The layer draws go to unless told otherwise.
+pub const DEFAULT_LAYER: LayerMask = 1;
+
+pub const ALL_LAYERS: LayerMask = LayerMask::MAX;
+
+/// What a view clears before drawing, only inside its own viewport.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClearMode {
+    /// Draws over whatever is there, for overlays.
+    None,
+    /// Keeps the color but lets the view's meshes draw in front of everything before it.
+    Depth,
+    /// Clears color and depth.
+    Color(Vec4)
+}
+
+/// How one camera draws into its part of the frame, see `Renderer::begin_view`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewSettings {
+    /// The part of the window or framebuffer drawn to, all of it when `None`.
+    pub viewport: Option<Viewport>,
+    pub clear: ClearMode,
+    pub culling_mask: LayerMask
+}
+
+impl ViewSettings {
+    pub fn new(viewport: Viewport) -> ViewSettings {
+        ViewSettings {
+            viewport: Some(viewport),
+            ..ViewSettings::default()
+        }
+    }
+
+    pub fn with_clear(mut self, clear: ClearMode) -> ViewSettings {
+        self.clear = clear;
+        self
+    }
+
+    pub fn with_culling_mask(mut self, culling_mask: LayerMask) -> ViewSettings {
+        self.culling_mask = culling_mask;
+        self
+    }
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        ViewSettings {
+            viewport: None,
+            clear: ClearMode::None,
+            culling_mask: ALL_LAYERS
+        }
+    }
+}
+
+/// Splits a window or framebuffer between `count` players, side by side for two and in a
+/// grid beyond that.
+pub fn split_screen(width: f32, height: f32, count: usize) -> Vec<Viewport> {
+    let columns = if count <= 2 { count.max(1) } else { (count as f32).sqrt().ceil() as usize };
+    let rows = count.max(1).div_ceil(columns);
+    let size = glm::vec2(width / columns as f32, height / rows as f32);
+    (0..count)
+        .map(|i| Viewport::new((i % columns) as f32 * size.x, (i / columns) as f32 * size.y, size.x, size.y))
+        .collect()
+}
+
+/// Where a virtual screen of `virtual_size` pixels is shown in a window: scaled by the
+/// largest whole number that fits and centered, leaving black bars around it. Windows
+/// smaller than the virtual screen show it unscaled and cropped.
+pub fn letterbox(window_size: Vec2, virtual_size: Vec2) -> Viewport {
+    let scale = (window_size.x / virtual_size.x).min(window_size.y / virtual_size.y).floor().max(1.0);
+    let size = virtual_size * scale;
+    Viewport::new(
+        ((window_size.x - size.x) * 0.5).floor(),
+        ((window_size.y - size.y) * 0.5).floor(),
+        size.x,
+        size.y
+    )
+}
+
+/// Converts a viewport with a top left origin to the x, y, width and height gl expects,
+/// rounded to whole pixels.
+pub(crate) fn to_gl_rect(viewport: &Viewport, target_height: f32) -> (i32, i32, i32, i32) {
+    let x = viewport.x.round() as i32;
+    let top = viewport.y.round() as i32;
+    let width = (viewport.x + viewport.width).round() as i32 - x;
+    let height = (viewport.y + viewport.height).round() as i32 - top;
+    (x, target_height.round() as i32 - top - height, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::camera::Viewport;
+    use crate::glm;
+    use crate::renderer::view::{letterbox, split_screen, to_gl_rect};
+
+    #[test]
+    fn split_screens_tile_the_window() {
+        let halves = split_screen(800.0, 600.0, 2);
+        assert_eq!(halves, vec![Viewport::new(0.0, 0.0, 400.0, 600.0), Viewport::new(400.0, 0.0, 400.0, 600.0)]);
+
+        let quarters = split_screen(800.0, 600.0, 3);
+        assert_eq!(quarters.len(), 3);
+        assert_eq!(quarters[2], Viewport::new(0.0, 300.0, 400.0, 300.0));
+
+        // gl counts rows from the bottom
+        assert_eq!(to_gl_rect(&quarters[0], 600.0), (0, 300, 400, 300));
+        assert_eq!(to_gl_rect(&quarters[2], 600.0), (0, 0, 400, 300));
+    }
+
+    #[test]
+    fn virtual_screens_scale_by_whole_numbers() {
+        let virtual_size = glm::vec2(320.0, 180.0);
+        assert_eq!(letterbox(glm::vec2(1280.0, 720.0), virtual_size), Viewport::new(0.0, 0.0, 1280.0, 720.0));
+        // 3x doesn't fit vertically, so 2x with bars on every side
+        assert_eq!(letterbox(glm::vec2(1000.0, 500.0), virtual_size), Viewport::new(180.0, 70.0, 640.0, 360.0));
+        assert_eq!(letterbox(glm::vec2(200.0, 100.0), virtual_size), Viewport::new(-60.0, -40.0, 320.0, 180.0));
+    }
+}
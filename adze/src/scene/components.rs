@@ -0,0 +1,300 @@
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::asset::Handle;
+use crate::glm::{self, Mat4, Vec2, Vec3, Vec4};
+use crate::renderer::sorting::SortKey;
+use crate::renderer::texture::Texture;
+use crate::scene::Entity;
+use crate::scene::serialize::PrefabSnapshot;
+
+/// A name for an entity, shown in tools and used to find it with `Scene::find_entity`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tag {
+    pub name: String
+}
+
+impl Tag {
+    pub fn new(name: &str) -> Tag {
+        Tag {
+            name: name.to_string()
+        }
+    }
+}
+
+/// Where an entity is relative to its parent, or to the world without one. Rotation is in
+/// radians around each axis, applied x then y then z.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Vec3,
+    pub scale: Vec3
+}
+
+impl Transform {
+    pub fn new(translation: Vec3) -> Transform {
+        Transform {
+            translation,
+            ..Transform::default()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Vec3) -> Transform {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Transform {
+        self.scale = scale;
+        self
+    }
+
+    pub fn get_matrix(&self) -> Mat4 {
+        let rotation = glm::rotate_x(&glm::rotate_y(&glm::rotate_z(&glm::identity(), self.rotation.z), self.rotation.y), self.rotation.x);
+        glm::translate(&glm::identity(), &self.translation) * rotation * glm::scale(&glm::identity(), &self.scale)
+    }
+}
+
+/// The inverse of `Transform::get_matrix`, exact for matrices built from a translation,
+/// rotation and scale. Shear, from rotating below a non-uniform scale, is dropped.
+pub(crate) fn decompose(matrix: &Mat4) -> Transform {
+    let column = |i: usize| glm::vec3(matrix[(0, i)], matrix[(1, i)], matrix[(2, i)]);
+    let mut scale = glm::vec3(glm::length(&column(0)), glm::length(&column(1)), glm::length(&column(2)));
+    // a mirrored matrix is kept as a negative x scale
+    if glm::dot(&column(0).cross(&column(1)), &column(2)) < 0.0 {
+        scale.x = -scale.x;
+    }
+    let x = column(0) / scale.x;
+    let y = column(1) / scale.y;
+    let z = column(2) / scale.z;
+
+    // the rotation is rz * ry * rx, its bottom left entry is -sin(ry)
+    let rotation_y = (-x.z).clamp(-1.0, 1.0).asin();
+    let rotation = if rotation_y.cos() > 1e-6 {
+        glm::vec3(y.z.atan2(z.z), rotation_y, x.y.atan2(x.x))
+    } else {
+        // looking straight up or down, x and z turn around the same axis
+        glm::vec3(0.0, rotation_y, (-y.x).atan2(y.y))
+    };
+
+    Transform {
+        translation: column(3),
+        rotation,
+        scale
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: glm::vec3(0.0, 0.0, 0.0),
+            rotation: glm::vec3(0.0, 0.0, 0.0),
+            scale: glm::vec3(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+/// Where an entity ends up in the world once its parents' transforms are applied. Kept up to
+/// date by the scene, see `Scene::update_world_transforms`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldTransform {
+    pub(crate) matrix: Mat4,
+    // the local transform the matrix was computed from, to notice when it changes
+    pub(crate) local: Transform
+}
+
+impl WorldTransform {
+    pub fn get_matrix(&self) -> Mat4 {
+        self.matrix
+    }
+
+    pub fn get_translation(&self) -> Vec3 {
+        glm::vec3(self.matrix[(0, 3)], self.matrix[(1, 3)], self.matrix[(2, 3)])
+    }
+}
+
+/// An entity's place in the hierarchy, changed with `Scene::set_parent`. Entities without one
+/// are roots.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Hierarchy {
+    pub(crate) parent: Option<Entity>,
+    pub(crate) children: Vec<Entity>
+}
+
+impl Hierarchy {
+    pub fn get_parent(&self) -> Option<Entity> {
+        self.parent
+    }
+
+    pub fn get_children(&self) -> &[Entity] {
+        &self.children
+    }
+}
+
+/// Marks the root of an instance of a prefab, see `SceneSerializer::instantiate`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefabInstance {
+    path: String,
+    // the instance as it was spawned, only what changed since is saved
+    original: Rc<PrefabSnapshot>
+}
+
+impl PrefabInstance {
+    pub(crate) fn new(path: &str, original: PrefabSnapshot) -> PrefabInstance {
+        PrefabInstance {
+            path: path.to_string(),
+            original: Rc::new(original)
+        }
+    }
+
+    pub(crate) fn get_original(&self) -> &PrefabSnapshot {
+        &self.original
+    }
+
+    /// The path of the prefab the instance was spawned from.
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// A rectangle in the entity's xy plane, centered on `offset`, telling the entity's script
+/// when it starts touching another one. Defaults to the quad drawn by a `SpriteRenderer`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoxCollider {
+    pub size: Vec2,
+    pub offset: Vec2
+}
+
+impl BoxCollider {
+    pub fn new(size: Vec2) -> BoxCollider {
+        BoxCollider {
+            size,
+            offset: glm::vec2(0.0, 0.0)
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> BoxCollider {
+        self.offset = offset;
+        self
+    }
+
+    /// The smallest and largest x and y of the box in the world, for a rotated box those of
+    /// the axis aligned box around it.
+    pub fn get_bounds(&self, world: &Mat4) -> (Vec2, Vec2) {
+        let half_size = self.size * 0.5;
+        let mut min = glm::vec2(f32::MAX, f32::MAX);
+        let mut max = glm::vec2(f32::MIN, f32::MIN);
+        for corner in [glm::vec2(-1.0, -1.0), glm::vec2(1.0, -1.0), glm::vec2(1.0, 1.0), glm::vec2(-1.0, 1.0)] {
+            let local = self.offset + half_size.component_mul(&corner);
+            let corner = world * glm::vec4(local.x, local.y, 0.0, 1.0);
+            min = glm::min2(&min, &corner.xy());
+            max = glm::max2(&max, &corner.xy());
+        }
+        (min, max)
+    }
+}
+
+impl Default for BoxCollider {
+    fn default() -> Self {
+        BoxCollider::new(glm::vec2(1.0, 1.0))
+    }
+}
+
+/// Draws a quad one unit wide at the entity's transform, tinted by `color`.
+#[derive(Clone, Debug)]
+pub struct SpriteRenderer {
+    pub color: Vec4,
+    pub texture: Option<Handle<Texture>>,
+    pub sort_key: SortKey
+}
+
+impl SpriteRenderer {
+    pub fn new(color: Vec4) -> SpriteRenderer {
+        SpriteRenderer {
+            color,
+            texture: None,
+            sort_key: SortKey::default()
+        }
+    }
+
+    pub fn with_texture(mut self, texture: Handle<Texture>) -> SpriteRenderer {
+        self.texture = Some(texture);
+        self
+    }
+
+    pub fn with_sort_key(mut self, sort_key: SortKey) -> SpriteRenderer {
+        self.sort_key = sort_key;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    /// `size` is the height of the view in world units.
+    Orthographic { size: f32, near: f32, far: f32 },
+    /// `fov` is the vertical field of view in radians.
+    Perspective { fov: f32, near: f32, far: f32 }
+}
+
+/// Lets the scene be drawn from the entity, looking down its -z axis. The scene is drawn from
+/// the first primary camera.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera {
+    pub projection: Projection,
+    pub primary: bool,
+    /// Keeps `aspect_ratio` when the viewport is resized, see `Scene::on_viewport_resize`.
+    pub fixed_aspect_ratio: bool,
+    pub aspect_ratio: f32
+}
+
+impl Camera {
+    pub fn orthographic(size: f32) -> Camera {
+        Camera {
+            projection: Projection::Orthographic { size, near: -1.0, far: 1.0 },
+            primary: true,
+            fixed_aspect_ratio: false,
+            aspect_ratio: 1.0
+        }
+    }
+
+    pub fn perspective(fov: f32) -> Camera {
+        Camera {
+            projection: Projection::Perspective { fov, near: 0.1, far: 1000.0 },
+            primary: true,
+            fixed_aspect_ratio: false,
+            aspect_ratio: 1.0
+        }
+    }
+
+    pub fn get_projection(&self) -> Mat4 {
+        match self.projection {
+            Projection::Orthographic { size, near, far } => {
+                let half_height = size * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                glm::ortho(-half_width, half_width, -half_height, half_height, near, far)
+            }
+            Projection::Perspective { fov, near, far } => glm::perspective(self.aspect_ratio, fov, near, far)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glm;
+    use crate::scene::components::{Camera, Transform};
+
+    #[test]
+    fn transforms_scale_then_rotate_then_translate() {
+        let transform = Transform::new(glm::vec3(1.0, 2.0, 0.0))
+            .with_rotation(glm::vec3(0.0, 0.0, std::f32::consts::FRAC_PI_2))
+            .with_scale(glm::vec3(2.0, 1.0, 1.0));
+        let corner = transform.get_matrix() * glm::vec4(1.0, 0.0, 0.0, 1.0);
+        assert!(glm::distance(&glm::vec3(corner.x, corner.y, corner.z), &glm::vec3(1.0, 4.0, 0.0)) < 1e-5);
+
+        let mut camera = Camera::orthographic(2.0);
+        camera.aspect_ratio = 2.0;
+        let edge = camera.get_projection() * glm::vec4(2.0, 1.0, 0.0, 1.0);
+        assert!(glm::distance(&glm::vec2(edge.x, edge.y), &glm::vec2(1.0, 1.0)) < 1e-5);
+    }
+}
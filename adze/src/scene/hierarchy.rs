@@ -0,0 +1,195 @@
+use crate::glm::{self, Mat4};
+use crate::scene::{Entity, Scene};
+use crate::scene::components::{self, Hierarchy, Transform, WorldTransform};
+
+impl Scene {
+    pub fn get_parent(&self, entity: Entity) -> Option<Entity> {
+        self.get_component::<Hierarchy>(entity)?.parent
+    }
+
+    pub fn get_children(&self, entity: Entity) -> &[Entity] {
+        self.get_component::<Hierarchy>(entity).map_or(&[], |hierarchy| &hierarchy.children)
+    }
+
+    /// The entities without a parent, in the order they were created.
+    pub fn get_root_entities(&self) -> Vec<Entity> {
+        self.get_entities().filter(|&entity| self.get_parent(entity).is_none()).collect()
+    }
+
+    /// Moves `child` under `parent`, or to the root with `None`, keeping where it is in the
+    /// world by changing its local transform. Panics if `parent` is `child` or one of its
+    /// descendants.
+    pub fn set_parent(&mut self, child: Entity, parent: Option<Entity>) {
+        if let Some(parent) = parent {
+            assert!(self.is_alive(parent), "{:?} was destroyed", parent);
+            assert!(!self.is_ancestor_or_self(child, parent), "{:?} can't be parented to its own descendant", child);
+        }
+        if self.get_parent(child) == parent {
+            return;
+        }
+
+        let world = self.compute_world_matrix(child);
+        let parent_world = parent.map_or_else(glm::identity, |parent| self.compute_world_matrix(parent));
+        self.attach(child, parent);
+
+        if self.has_component::<Transform>(child) {
+            self.add_component(child, components::decompose(&(glm::inverse(&parent_world) * world)));
+        }
+    }
+
+    /// Links `child` to `parent` leaving its local transform as it is, for hierarchies read
+    /// from files.
+    pub(crate) fn attach(&mut self, child: Entity, parent: Option<Entity>) {
+        if let Some(old_parent) = self.get_parent(child) {
+            self.get_component_mut::<Hierarchy>(old_parent).unwrap().children.retain(|&other| other != child);
+        }
+        if let Some(parent) = parent {
+            self.hierarchy_mut(parent).children.push(child);
+        }
+        self.hierarchy_mut(child).parent = parent;
+    }
+
+    /// `entity` and every entity below it, parents before their children.
+    pub fn get_descendants(&self, entity: Entity) -> Vec<Entity> {
+        let mut descendants = vec![entity];
+        let mut i = 0;
+        while let Some(&next) = descendants.get(i) {
+            descendants.extend_from_slice(self.get_children(next));
+            i += 1;
+        }
+        descendants
+    }
+
+    /// Recomputes the world transforms of the entities whose transform, or whose parents'
+    /// transforms, changed since the last call. Runs before the scene is drawn and after the
+    /// systems.
+    pub fn update_world_transforms(&mut self) {
+        self.propagate_transforms();
+    }
+
+    /// Returns how many world transforms were recomputed.
+    pub(crate) fn propagate_transforms(&mut self) -> usize {
+        let mut recomputed = 0;
+        let mut stack: Vec<(Entity, Mat4, bool)> = self.get_root_entities()
+            .into_iter()
+            .map(|root| (root, glm::identity(), false))
+            .collect();
+
+        while let Some((entity, parent_world, parent_changed)) = stack.pop() {
+            let local = self.get_component::<Transform>(entity).copied().unwrap_or_default();
+            let cached = self.get_component::<WorldTransform>(entity).copied();
+            let changed = parent_changed || cached.is_none_or(|cached| cached.local != local);
+
+            let world = if changed {
+                recomputed += 1;
+                let matrix = parent_world * local.get_matrix();
+                self.add_component(entity, WorldTransform { matrix, local });
+                matrix
+            } else {
+                cached.unwrap().matrix
+            };
+
+            for &child in self.get_children(entity) {
+                stack.push((child, world, changed));
+            }
+        }
+        recomputed
+    }
+
+    /// Walks up the parents without the cache, which may be out of date between updates.
+    pub(crate) fn compute_world_matrix(&self, entity: Entity) -> Mat4 {
+        let mut matrix = glm::identity();
+        let mut next = Some(entity);
+        while let Some(entity) = next {
+            if let Some(transform) = self.get_component::<Transform>(entity) {
+                matrix = transform.get_matrix() * matrix;
+            }
+            next = self.get_parent(entity);
+        }
+        matrix
+    }
+
+    pub(crate) fn is_ancestor_or_self(&self, ancestor: Entity, mut entity: Entity) -> bool {
+        loop {
+            if entity == ancestor {
+                return true;
+            }
+            match self.get_parent(entity) {
+                Some(parent) => entity = parent,
+                None => return false
+            }
+        }
+    }
+
+    fn hierarchy_mut(&mut self, entity: Entity) -> &mut Hierarchy {
+        if !self.has_component::<Hierarchy>(entity) {
+            self.add_component(entity, Hierarchy::default());
+        }
+        self.get_component_mut::<Hierarchy>(entity).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glm;
+    use crate::scene::Scene;
+    use crate::scene::components::{Transform, WorldTransform};
+
+    fn world_translation(scene: &Scene, entity: crate::scene::Entity) -> glm::Vec3 {
+        scene.get_component::<WorldTransform>(entity).unwrap().get_translation()
+    }
+
+    #[test]
+    fn children_follow_their_parents_and_keep_their_place_when_reparented() {
+        let mut scene = Scene::new();
+        let ship = scene.create_entity("ship");
+        scene.add_component(ship, Transform::new(glm::vec3(10.0, 0.0, 0.0)).with_rotation(glm::vec3(0.0, 0.0, std::f32::consts::FRAC_PI_2)));
+        let turret = scene.create_entity("turret");
+        scene.add_component(turret, Transform::new(glm::vec3(1.0, 0.0, 0.0)));
+        let world = scene.create_entity("world");
+
+        scene.set_parent(turret, Some(ship));
+        assert_eq!(scene.propagate_transforms(), 3);
+        // reparenting kept the turret where it was
+        assert!(glm::distance(&world_translation(&scene, turret), &glm::vec3(1.0, 0.0, 0.0)) < 1e-5);
+
+        scene.get_component_mut::<Transform>(ship).unwrap().translation.y = 5.0;
+        // only the ship and the turret below it changed
+        assert_eq!(scene.propagate_transforms(), 2);
+        assert!(glm::distance(&world_translation(&scene, turret), &glm::vec3(1.0, 5.0, 0.0)) < 1e-5);
+        assert_eq!(scene.propagate_transforms(), 0);
+
+        scene.set_parent(turret, Some(world));
+        scene.propagate_transforms();
+        assert!(glm::distance(&world_translation(&scene, turret), &glm::vec3(1.0, 5.0, 0.0)) < 1e-5);
+        assert_eq!(scene.get_children(ship), &[]);
+        assert_eq!(scene.get_children(world), &[turret]);
+    }
+
+    #[test]
+    fn destroying_a_parent_destroys_its_children() {
+        let mut scene = Scene::new();
+        let root = scene.create_entity("root");
+        let child = scene.create_entity("child");
+        let grandchild = scene.create_entity("grandchild");
+        let sibling = scene.create_entity("sibling");
+        scene.set_parent(child, Some(root));
+        scene.set_parent(grandchild, Some(child));
+        scene.set_parent(sibling, Some(root));
+
+        scene.destroy_entity(child);
+        assert!(!scene.is_alive(child) && !scene.is_alive(grandchild));
+        assert_eq!(scene.get_children(root), &[sibling]);
+        assert_eq!(scene.get_root_entities(), vec![root]);
+    }
+
+    #[test]
+    #[should_panic(expected = "own descendant")]
+    fn entities_cannot_be_parented_to_their_descendants() {
+        let mut scene = Scene::new();
+        let parent = scene.create_entity("parent");
+        let child = scene.create_entity("child");
+        scene.set_parent(child, Some(parent));
+        scene.set_parent(parent, Some(child));
+    }
+}
@@ -0,0 +1,302 @@
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+
+use crate::glm::{self, Mat4};
+use crate::renderer::Renderer;
+use crate::renderer::camera;
+
+use components::{Camera, Hierarchy, SpriteRenderer, Tag, Transform, WorldTransform};
+use query::{Query, QueryIter};
+use storage::{AnyStorage, ComponentStorage};
+
+pub mod components;
+pub mod query;
+pub mod script;
+pub mod serialize;
+mod hierarchy;
+mod storage;
+
+/// An id for a thing in a scene, made of an index and the generation of that index so ids
+/// of destroyed entities never match the entities reusing their index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32
+}
+
+/// Game logic run on the scene every tick, see `Scene::add_system`. Closures taking the
+/// scene and the tick's length in seconds are systems.
+pub trait System {
+    fn run(&mut self, scene: &mut Scene, dt: f32);
+}
+
+impl<F: FnMut(&mut Scene, f32)> System for F {
+    fn run(&mut self, scene: &mut Scene, dt: f32) {
+        self(scene, dt)
+    }
+}
+
+struct EntitySlot {
+    generation: u32,
+    alive: bool
+}
+
+/// Draws from the projection and view of a camera entity.
+struct SceneCamera {
+    projection_view: Mat4
+}
+
+impl camera::Camera for SceneCamera {
+    fn get_projection_view(&self) -> Mat4 {
+        self.projection_view
+    }
+}
+
+/// Entities and the components attached to them, along with the systems updating them. Any
+/// `'static` type can be a component, an entity holds at most one of each type.
+pub struct Scene {
+    slots: Vec<EntitySlot>,
+    free_indices: Vec<u32>,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    systems: Vec<Box<dyn System>>,
+    // the pairs of colliders touching at the last update, smallest entity first
+    touching: HashSet<(Entity, Entity)>,
+    viewport_aspect_ratio: f32
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            slots: vec![],
+            free_indices: vec![],
+            storages: HashMap::new(),
+            systems: vec![],
+            touching: HashSet::new(),
+            viewport_aspect_ratio: 1.0
+        }
+    }
+
+    /// Creates an entity with a `Tag` and a `Transform` at the origin.
+    pub fn create_entity(&mut self, name: &str) -> Entity {
+        let entity = self.create_empty_entity();
+        self.add_component(entity, Tag::new(name));
+        self.add_component(entity, Transform::default());
+        entity
+    }
+
+    pub fn create_empty_entity(&mut self) -> Entity {
+        match self.free_indices.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.alive = true;
+                Entity { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(EntitySlot { generation: 0, alive: true });
+                Entity { index: self.slots.len() as u32 - 1, generation: 0 }
+            }
+        }
+    }
+
+    /// Removes the entity, its components and its children, after calling `on_destroy` on
+    /// their scripts. Does nothing if it was already destroyed.
+    pub fn destroy_entity(&mut self, entity: Entity) {
+        if !self.is_alive(entity) {
+            return;
+        }
+        self.destroy_scripts(entity);
+        // the scripts may have destroyed it themselves
+        if !self.is_alive(entity) {
+            return;
+        }
+        if let Some(parent) = self.get_parent(entity) {
+            self.get_component_mut::<Hierarchy>(parent).unwrap().children.retain(|&child| child != entity);
+        }
+
+        for entity in self.get_descendants(entity) {
+            for storage in self.storages.values_mut() {
+                storage.remove_entity(entity);
+            }
+            let slot = &mut self.slots[entity.index as usize];
+            slot.alive = false;
+            slot.generation += 1;
+            self.free_indices.push(entity.index);
+        }
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.slots.get(entity.index as usize).is_some_and(|slot| slot.alive && slot.generation == entity.generation)
+    }
+
+    /// Every living entity, in the order of their indices.
+    pub fn get_entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.slots.iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.alive)
+            .map(|(index, slot)| Entity { index: index as u32, generation: slot.generation })
+    }
+
+    pub fn get_entity_count(&self) -> usize {
+        self.slots.len() - self.free_indices.len()
+    }
+
+    /// The first entity tagged `name`.
+    pub fn find_entity(&self, name: &str) -> Option<Entity> {
+        self.get_entities().find(|&entity| self.get_component::<Tag>(entity).is_some_and(|tag| tag.name == name))
+    }
+
+    /// Adds a component to a living entity, returning the one of the same type it replaces.
+    pub fn add_component<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        assert!(self.is_alive(entity), "{:?} was destroyed", entity);
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(ComponentStorage::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<T>>()
+            .unwrap()
+            .insert(entity, component)
+    }
+
+    pub fn remove_component<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        self.get_storage_mut::<T>()?.remove(entity)
+    }
+
+    pub fn has_component<T: 'static>(&self, entity: Entity) -> bool {
+        self.get_component::<T>(entity).is_some()
+    }
+
+    pub fn get_component<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        self.get_storage::<T>()?.get(entity)
+    }
+
+    pub fn get_component_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_storage_mut::<T>()?.get_mut(entity)
+    }
+
+    /// Iterates over the entities having every component in `Q`, see `Query`.
+    pub fn query<'s, Q: Query<'s>>(&'s mut self) -> QueryIter<'s, Q> {
+        QueryIter::new(self)
+    }
+
+    pub(crate) fn get_storage<T: 'static>(&self) -> Option<&ComponentStorage<T>> {
+        self.storages.get(&TypeId::of::<T>()).map(|storage| storage.as_any().downcast_ref().unwrap())
+    }
+
+    pub(crate) fn get_storage_mut<T: 'static>(&mut self) -> Option<&mut ComponentStorage<T>> {
+        self.storages.get_mut(&TypeId::of::<T>()).map(|storage| storage.as_any_mut().downcast_mut().unwrap())
+    }
+
+    /// Adds a system to run every `on_update`, after the systems added before it.
+    pub fn add_system<S: System + 'static>(&mut self, system: S) {
+        self.systems.push(Box::new(system));
+    }
+
+    /// Updates the scripts, runs every system once in the order they were added, then updates
+    /// the world transforms and tells the scripts about new collisions.
+    pub fn on_update(&mut self, dt: f32) {
+        self.update_scripts(dt);
+
+        // systems get the whole scene, including the right to add more systems
+        let mut systems = std::mem::take(&mut self.systems);
+        for system in systems.iter_mut() {
+            system.run(self, dt);
+        }
+        systems.append(&mut self.systems);
+        self.systems = systems;
+
+        self.update_world_transforms();
+        self.detect_collisions();
+    }
+
+    /// Gives cameras without a fixed aspect ratio the one of the viewport.
+    pub fn on_viewport_resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.viewport_aspect_ratio = width as f32 / height as f32;
+        let aspect_ratio = self.viewport_aspect_ratio;
+        for (_, camera) in self.query::<&mut Camera>() {
+            if !camera.fixed_aspect_ratio {
+                camera.aspect_ratio = aspect_ratio;
+            }
+        }
+    }
+
+    /// Draws the sprites from the primary camera. Nothing is drawn without one.
+    pub fn on_render(&mut self, renderer: &mut Renderer) {
+        self.update_world_transforms();
+
+        let aspect_ratio = self.viewport_aspect_ratio;
+        let camera = self.query::<(&mut Camera, &WorldTransform)>()
+            .find(|(_, (camera, _))| camera.primary)
+            .map(|(_, (camera, transform))| {
+                // cameras added since the last resize haven't been given the viewport's yet
+                if !camera.fixed_aspect_ratio {
+                    camera.aspect_ratio = aspect_ratio;
+                }
+                SceneCamera {
+                    projection_view: camera.get_projection() * glm::inverse(&transform.get_matrix())
+                }
+            });
+        let camera = match camera {
+            Some(camera) => camera,
+            None => return
+        };
+
+        renderer.begin(&camera);
+        for (_, (transform, sprite)) in self.query::<(&WorldTransform, &SpriteRenderer)>() {
+            renderer.set_sort_key(sprite.sort_key);
+            renderer.draw_sprite(&transform.get_matrix(), sprite.color, sprite.texture.as_ref());
+        }
+        renderer.end();
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glm;
+    use crate::scene::Scene;
+    use crate::scene::components::{Tag, Transform};
+
+    #[test]
+    fn destroyed_entities_are_not_confused_with_new_ones() {
+        let mut scene = Scene::new();
+        let player = scene.create_entity("player");
+        let enemy = scene.create_entity("enemy");
+        scene.destroy_entity(player);
+
+        let reused = scene.create_entity("pickup");
+        assert!(!scene.is_alive(player));
+        assert!(scene.is_alive(reused));
+        assert!(scene.get_component::<Tag>(player).is_none());
+        assert_eq!(scene.get_component::<Tag>(reused), Some(&Tag::new("pickup")));
+        assert_eq!(scene.find_entity("enemy"), Some(enemy));
+        assert_eq!(scene.get_entity_count(), 2);
+    }
+
+    #[test]
+    fn systems_run_in_order_every_update() {
+        let mut scene = Scene::new();
+        let entity = scene.create_entity("mover");
+        scene.add_system(|scene: &mut Scene, dt: f32| {
+            for (_, transform) in scene.query::<&mut Transform>() {
+                transform.translation.x += dt;
+            }
+        });
+        scene.add_system(|scene: &mut Scene, _: f32| {
+            for (_, transform) in scene.query::<&mut Transform>() {
+                transform.translation.y = transform.translation.x * 2.0;
+            }
+        });
+
+        scene.on_update(0.5);
+        scene.on_update(0.5);
+        assert_eq!(scene.get_component::<Transform>(entity).unwrap().translation, glm::vec3(1.0, 2.0, 0.0));
+    }
+}
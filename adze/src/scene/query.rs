@@ -0,0 +1,218 @@
+use std::any::{self, TypeId};
+use std::marker::PhantomData;
+
+use crate::scene::{Entity, Scene};
+use crate::scene::storage::ComponentStorage;
+
+/// A set of components to iterate over, `&T` to read one and `&mut T` to change it. Tuples
+/// of up to six ask for entities having all of them:
+///
+/// ```ignore
+/// for (entity, (transform, sprite)) in scene.query::<(&Transform, &mut SpriteRenderer)>() {
+/// }
+/// ```
+pub trait Query<'s> {
+    type Item;
+    #[doc(hidden)]
+    type State: Copy;
+
+    /// The component types borrowed and whether they are borrowed mutably.
+    #[doc(hidden)]
+    fn access(access: &mut Vec<(TypeId, &'static str, bool)>);
+
+    /// `None` when a component type was never added, so nothing can match.
+    #[doc(hidden)]
+    fn state(scene: &mut Scene) -> Option<Self::State>;
+
+    /// The entities to check, those of the least common component.
+    #[doc(hidden)]
+    unsafe fn entities(state: Self::State) -> &'s [Entity];
+
+    #[doc(hidden)]
+    unsafe fn fetch(state: Self::State, entity: Entity) -> Option<Self::Item>;
+}
+
+impl<'s, T: 'static> Query<'s> for &'s T {
+    type Item = &'s T;
+    type State = *const ComponentStorage<T>;
+
+    fn access(access: &mut Vec<(TypeId, &'static str, bool)>) {
+        access.push((TypeId::of::<T>(), any::type_name::<T>(), false));
+    }
+
+    fn state(scene: &mut Scene) -> Option<Self::State> {
+        scene.get_storage::<T>().map(|storage| storage as *const _)
+    }
+
+    unsafe fn entities(state: Self::State) -> &'s [Entity] {
+        (*state).get_entities()
+    }
+
+    unsafe fn fetch(state: Self::State, entity: Entity) -> Option<Self::Item> {
+        (*state).get(entity)
+    }
+}
+
+impl<'s, T: 'static> Query<'s> for &'s mut T {
+    type Item = &'s mut T;
+    type State = *mut ComponentStorage<T>;
+
+    fn access(access: &mut Vec<(TypeId, &'static str, bool)>) {
+        access.push((TypeId::of::<T>(), any::type_name::<T>(), true));
+    }
+
+    fn state(scene: &mut Scene) -> Option<Self::State> {
+        scene.get_storage_mut::<T>().map(|storage| storage as *mut _)
+    }
+
+    unsafe fn entities(state: Self::State) -> &'s [Entity] {
+        (*state).get_entities()
+    }
+
+    unsafe fn fetch(state: Self::State, entity: Entity) -> Option<Self::Item> {
+        ComponentStorage::get_mut_raw(state, entity)
+    }
+}
+
+macro_rules! impl_query_for_tuple {
+    ($($name:ident),+) => {
+        impl<'s, $($name: Query<'s>),+> Query<'s> for ($($name,)+) {
+            type Item = ($($name::Item,)+);
+            type State = ($($name::State,)+);
+
+            fn access(access: &mut Vec<(TypeId, &'static str, bool)>) {
+                $($name::access(access);)+
+            }
+
+            fn state(scene: &mut Scene) -> Option<Self::State> {
+                Some(($($name::state(scene)?,)+))
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn entities(state: Self::State) -> &'s [Entity] {
+                let ($($name,)+) = state;
+                let mut entities: Option<&'s [Entity]> = None;
+                $(
+                    let candidates = $name::entities($name);
+                    if entities.map_or(true, |entities| candidates.len() < entities.len()) {
+                        entities = Some(candidates);
+                    }
+                )+
+                entities.unwrap()
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch(state: Self::State, entity: Entity) -> Option<Self::Item> {
+                let ($($name,)+) = state;
+                Some(($($name::fetch($name, entity)?,)+))
+            }
+        }
+    };
+}
+
+impl_query_for_tuple!(A);
+impl_query_for_tuple!(A, B);
+impl_query_for_tuple!(A, B, C);
+impl_query_for_tuple!(A, B, C, D);
+impl_query_for_tuple!(A, B, C, D, E);
+impl_query_for_tuple!(A, B, C, D, E, F);
+
+/// Panics when a query would hand out a component mutably alongside another reference to it.
+pub(crate) fn check_access(access: &[(TypeId, &'static str, bool)]) {
+    for (i, (type_id, name, mutable)) in access.iter().enumerate() {
+        let aliased = access[..i].iter().any(|(other, _, other_mutable)| other == type_id && (*mutable || *other_mutable));
+        if aliased {
+            panic!("query borrows {} mutably more than once", name);
+        }
+    }
+}
+
+/// Iterates over the entities matching a query, see `Scene::query`.
+pub struct QueryIter<'s, Q: Query<'s>> {
+    state: Option<Q::State>,
+    entities: &'s [Entity],
+    index: usize,
+    marker: PhantomData<&'s mut Scene>
+}
+
+impl<'s, Q: Query<'s>> QueryIter<'s, Q> {
+    pub(crate) fn new(scene: &'s mut Scene) -> QueryIter<'s, Q> {
+        let mut access = vec![];
+        Q::access(&mut access);
+        check_access(&access);
+
+        let state = Q::state(scene);
+        // the storages stay in place while the scene is borrowed by the iterator
+        let entities = state.map_or(&[][..], |state| unsafe { Q::entities(state) });
+
+        QueryIter {
+            state,
+            entities,
+            index: 0,
+            marker: PhantomData
+        }
+    }
+}
+
+impl<'s, Q: Query<'s>> Iterator for QueryIter<'s, Q> {
+    type Item = (Entity, Q::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let state = self.state?;
+        while let Some(&entity) = self.entities.get(self.index) {
+            self.index += 1;
+            // every entity is visited once, so mutable components are never handed out twice
+            if let Some(item) = unsafe { Q::fetch(state, entity) } {
+                return Some((entity, item));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scene::Scene;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    struct Frozen;
+
+    #[test]
+    fn queries_match_entities_with_every_component() {
+        let mut scene = Scene::new();
+        let moving = scene.create_empty_entity();
+        scene.add_component(moving, Position(0));
+        scene.add_component(moving, Velocity(2));
+        let still = scene.create_empty_entity();
+        scene.add_component(still, Position(5));
+        let frozen = scene.create_empty_entity();
+        scene.add_component(frozen, Position(1));
+        scene.add_component(frozen, Velocity(3));
+        scene.add_component(frozen, Frozen);
+
+        for (_, (position, velocity)) in scene.query::<(&mut Position, &Velocity)>() {
+            position.0 += velocity.0;
+        }
+        assert_eq!(scene.get_component::<Position>(moving), Some(&Position(2)));
+        assert_eq!(scene.get_component::<Position>(still), Some(&Position(5)));
+        assert_eq!(scene.get_component::<Position>(frozen), Some(&Position(4)));
+
+        let frozen_entities: Vec<_> = scene.query::<(&Position, &Frozen)>().map(|(entity, _)| entity).collect();
+        assert_eq!(frozen_entities, vec![frozen]);
+        assert_eq!(scene.query::<&String>().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "mutably more than once")]
+    fn queries_cannot_alias_components() {
+        let mut scene = Scene::new();
+        let entity = scene.create_empty_entity();
+        scene.add_component(entity, Position(0));
+        let _ = scene.query::<(&mut Position, &Position)>().count();
+    }
+}
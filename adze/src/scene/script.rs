@@ -0,0 +1,260 @@
+use std::mem;
+
+use glutin::event::VirtualKeyCode;
+
+use crate::app::App;
+use crate::glm::{self, Vec2};
+use crate::scene::{Entity, Scene};
+use crate::scene::components::{BoxCollider, WorldTransform};
+
+/// Gameplay code for one entity, attached with a `NativeScript`. Every method has a default
+/// that does nothing.
+pub trait ScriptableEntity: 'static {
+    /// Called on the first update after the script is attached, before its first `on_update`.
+    fn on_create(&mut self, _ctx: &mut ScriptContext) {}
+
+    /// Called every `Scene::on_update`, before the systems run.
+    fn on_update(&mut self, _ctx: &mut ScriptContext, _dt: f32) {}
+
+    /// Called when the entity is destroyed, while its components can still be read.
+    fn on_destroy(&mut self, _ctx: &mut ScriptContext) {}
+
+    /// Called when the entity's `BoxCollider` starts touching the one of `other`.
+    fn on_collision(&mut self, _ctx: &mut ScriptContext, _other: Entity) {}
+}
+
+/// Runs a script on the entity it's added to.
+pub struct NativeScript {
+    // taken out of the scene while it runs
+    script: Option<Box<dyn ScriptableEntity>>,
+    created: bool
+}
+
+impl NativeScript {
+    pub fn new<S: ScriptableEntity>(script: S) -> NativeScript {
+        NativeScript {
+            script: Some(Box::new(script)),
+            created: false
+        }
+    }
+}
+
+/// What a script is given to change its entity and the rest of the scene.
+pub struct ScriptContext<'s> {
+    scene: &'s mut Scene,
+    entity: Entity,
+    destroy: bool
+}
+
+impl<'s> ScriptContext<'s> {
+    /// The entity the script is attached to.
+    pub fn get_entity(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn get_component<T: 'static>(&self) -> Option<&T> {
+        self.scene.get_component(self.entity)
+    }
+
+    pub fn get_component_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.scene.get_component_mut(self.entity)
+    }
+
+    pub fn add_component<T: 'static>(&mut self, component: T) -> Option<T> {
+        self.scene.add_component(self.entity, component)
+    }
+
+    pub fn remove_component<T: 'static>(&mut self) -> Option<T> {
+        self.scene.remove_component(self.entity)
+    }
+
+    pub fn get_scene(&self) -> &Scene {
+        self.scene
+    }
+
+    pub fn get_scene_mut(&mut self) -> &mut Scene {
+        self.scene
+    }
+
+    /// Destroys the entity once the script returns. Destroying it through the scene instead
+    /// calls `on_destroy` after its components are gone.
+    pub fn destroy(&mut self) {
+        self.destroy = true;
+    }
+
+    pub fn is_key_pressed(&self, key_code: VirtualKeyCode) -> bool {
+        App::is_key_pressed(key_code)
+    }
+
+    /// The cursor position in pixels from the top left of the window.
+    pub fn get_mouse_position(&self) -> Vec2 {
+        App::get_mouse_position()
+    }
+}
+
+impl Scene {
+    /// Creates the scripts added since the last update and updates every script.
+    pub(crate) fn update_scripts(&mut self, dt: f32) {
+        let entities = self.get_storage::<NativeScript>().map_or(vec![], |storage| storage.get_entities().to_vec());
+        for entity in entities {
+            let created = match self.get_component_mut::<NativeScript>(entity) {
+                Some(script) => mem::replace(&mut script.created, true),
+                None => continue
+            };
+            self.run_script(entity, |script, ctx| {
+                if !created {
+                    script.on_create(ctx);
+                }
+                // the script may have destroyed itself when it was created
+                if !ctx.destroy && ctx.scene.is_alive(entity) {
+                    script.on_update(ctx, dt);
+                }
+            });
+        }
+    }
+
+    /// Calls `on_destroy` on the created scripts of `entity` and its descendants.
+    pub(crate) fn destroy_scripts(&mut self, entity: Entity) {
+        for entity in self.get_descendants(entity) {
+            if self.get_component::<NativeScript>(entity).is_some_and(|script| script.created) {
+                self.run_script(entity, |script, ctx| script.on_destroy(ctx));
+            }
+        }
+    }
+
+    /// Tells the created scripts of the entities whose colliders started touching since the
+    /// last update. Every pair of colliders is checked, which is fine for a few hundred of them.
+    pub(crate) fn detect_collisions(&mut self) {
+        let colliders: Vec<(Entity, Vec2, Vec2)> = self.query::<(&BoxCollider, &WorldTransform)>()
+            .map(|(entity, (collider, transform))| {
+                let (min, max) = collider.get_bounds(&transform.get_matrix());
+                (entity, min, max)
+            })
+            .collect();
+
+        let mut touching = vec![];
+        for (i, &(a, a_min, a_max)) in colliders.iter().enumerate() {
+            for &(b, b_min, b_max) in colliders[i + 1..].iter() {
+                let overlap = glm::min2(&a_max, &b_max) - glm::max2(&a_min, &b_min);
+                if overlap.x > 0.0 && overlap.y > 0.0 {
+                    touching.push((a.min(b), a.max(b)));
+                }
+            }
+        }
+
+        let started: Vec<(Entity, Entity)> = touching.iter().copied().filter(|pair| !self.touching.contains(pair)).collect();
+        self.touching = touching.into_iter().collect();
+        for (a, b) in started {
+            for (entity, other) in [(a, b), (b, a)] {
+                // an earlier collision may have destroyed either of them, scripts added since
+                // the scripts were updated aren't created yet
+                let created = self.get_component::<NativeScript>(entity).is_some_and(|script| script.created);
+                if created && self.is_alive(other) {
+                    self.run_script(entity, |script, ctx| script.on_collision(ctx, other));
+                }
+            }
+        }
+    }
+
+    /// Takes the entity's script out of the scene to give it the whole scene, then puts it
+    /// back unless the entity was destroyed or given another script meanwhile.
+    fn run_script<F: FnOnce(&mut dyn ScriptableEntity, &mut ScriptContext)>(&mut self, entity: Entity, f: F) {
+        let mut script = match self.get_component_mut::<NativeScript>(entity).and_then(|script| script.script.take()) {
+            Some(script) => script,
+            None => return
+        };
+
+        let mut ctx = ScriptContext { scene: self, entity, destroy: false };
+        f(script.as_mut(), &mut ctx);
+        let destroy = ctx.destroy;
+
+        if let Some(native_script) = self.get_component_mut::<NativeScript>(entity) {
+            if native_script.script.is_none() {
+                native_script.script = Some(script);
+            }
+        } else if !self.is_alive(entity) {
+            // destroyed through the scene while the script was out of it
+            script.on_destroy(&mut ScriptContext { scene: self, entity, destroy: false });
+        }
+        if destroy {
+            self.destroy_entity(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::glm;
+    use crate::scene::{Entity, Scene};
+    use crate::scene::components::{BoxCollider, Tag, Transform};
+    use crate::scene::script::{NativeScript, ScriptContext, ScriptableEntity};
+
+    struct Mover {
+        events: Rc<RefCell<Vec<String>>>
+    }
+
+    impl ScriptableEntity for Mover {
+        fn on_create(&mut self, ctx: &mut ScriptContext) {
+            self.events.borrow_mut().push(format!("create {}", ctx.get_component::<Tag>().unwrap().name));
+        }
+
+        fn on_update(&mut self, ctx: &mut ScriptContext, dt: f32) {
+            ctx.get_component_mut::<Transform>().unwrap().translation.x += dt;
+        }
+
+        fn on_destroy(&mut self, ctx: &mut ScriptContext) {
+            self.events.borrow_mut().push(format!("destroy {}", ctx.get_component::<Tag>().unwrap().name));
+        }
+
+        fn on_collision(&mut self, ctx: &mut ScriptContext, other: Entity) {
+            let other = ctx.get_scene().get_component::<Tag>(other).unwrap().name.clone();
+            self.events.borrow_mut().push(format!("hit {}", other));
+            ctx.destroy();
+        }
+    }
+
+    #[test]
+    fn scripts_move_their_entity_and_hear_about_collisions() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut scene = Scene::new();
+        let mover = scene.create_entity("mover");
+        scene.add_component(mover, BoxCollider::default());
+        scene.add_component(mover, NativeScript::new(Mover { events: events.clone() }));
+        let wall = scene.create_entity("wall");
+        scene.add_component(wall, Transform::new(glm::vec3(2.0, 0.0, 0.0)));
+        scene.add_component(wall, BoxCollider::default());
+
+        scene.on_update(0.5);
+        scene.on_update(0.5);
+        assert_eq!(scene.get_component::<Transform>(mover).unwrap().translation.x, 1.0);
+        assert_eq!(*events.borrow(), vec!["create mover"]);
+
+        // the mover reaches the wall and destroys itself
+        scene.on_update(0.25);
+        assert!(!scene.is_alive(mover));
+        assert_eq!(*events.borrow(), vec!["create mover", "hit wall", "destroy mover"]);
+        scene.on_update(0.25);
+        assert_eq!(events.borrow().len(), 3);
+    }
+
+    #[test]
+    fn scripts_are_not_told_about_collisions_or_destruction_before_being_created() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut scene = Scene::new();
+        let mover = scene.create_entity("mover");
+        scene.add_component(mover, BoxCollider::default());
+        scene.add_component(mover, NativeScript::new(Mover { events: events.clone() }));
+        let wall = scene.create_entity("wall");
+        scene.add_component(wall, BoxCollider::default());
+
+        // as if a system had added the mover on top of the wall
+        scene.update_world_transforms();
+        scene.detect_collisions();
+        assert!(scene.is_alive(mover));
+        scene.destroy_entity(mover);
+        assert!(events.borrow().is_empty());
+    }
+}
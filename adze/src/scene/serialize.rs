@@ -0,0 +1,816 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::{fmt, io};
+
+use ron::ser::PrettyConfig;
+use ron::value::RawValue;
+use serde::de::{DeserializeOwned, Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+
+use crate::asset::AssetServer;
+use crate::glm::Vec4;
+use crate::renderer::sorting::SortKey;
+use crate::renderer::texture::SamplerDesc;
+use crate::scene::{Entity, Scene};
+use crate::scene::components::{BoxCollider, Camera, PrefabInstance, SpriteRenderer, Tag, Transform};
+
+/// The version scenes are saved with. Older files are brought up to date by the migrations
+/// added with `SceneSerializer::add_migration` as they are loaded.
+pub const SCENE_VERSION: u32 = 1;
+
+/// A component that can be saved in scene files, registered with
+/// `SceneSerializer::register`. Components are saved as their `Data`, which holds paths in
+/// place of asset handles.
+pub trait SceneComponent: Sized + 'static {
+    /// The name the component is saved under. Renaming it breaks existing files unless the
+    /// old name is kept with `SceneSerializer::rename_component`.
+    const NAME: &'static str;
+    type Data: Serialize + DeserializeOwned;
+
+    fn save(&self, assets: &AssetServer) -> Self::Data;
+
+    fn load(data: Self::Data, assets: &AssetServer) -> Self;
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    /// The file couldn't be read or written.
+    Io(String, io::Error),
+    /// The file isn't valid RON or isn't laid out like a scene.
+    Format(String),
+    /// The file was saved by a newer version of the engine.
+    UnsupportedVersion(u32),
+    UnknownComponent(String),
+    /// A component's data doesn't match its type.
+    Component(String, String),
+    /// An entity's parent is missing or isn't saved before it.
+    InvalidParent(u32),
+    /// A prefab contains an instance of itself.
+    RecursivePrefab(String)
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(path, err) => write!(f, "Could not access scene {}: {}", path, err),
+            SceneError::Format(err) => write!(f, "Invalid scene: {}", err),
+            SceneError::UnsupportedVersion(version) => write!(f, "Scene version {} is newer than the supported version {}", version, SCENE_VERSION),
+            SceneError::UnknownComponent(name) => write!(f, "Unknown component {}, it may need to be registered", name),
+            SceneError::Component(name, err) => write!(f, "Invalid {} component: {}", name, err),
+            SceneError::InvalidParent(id) => write!(f, "Invalid parent {}", id),
+            SceneError::RecursivePrefab(path) => write!(f, "Prefab {} contains itself", path)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io(_, err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+/// An entity as it is written in a scene file, handed to migrations to rewrite. Ids are
+/// only meaningful within one file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedEntity {
+    pub id: u32,
+    pub parent: Option<u32>,
+    /// Set on entities spawned from a prefab, their components then only hold the fields
+    /// changed from the prefab.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefab: Option<PrefabSource>,
+    /// Each component's data as RON, by component name.
+    pub components: BTreeMap<String, Box<RawValue>>
+}
+
+/// Where a saved entity spawned from a prefab comes from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PrefabSource {
+    /// The root of an instance of the prefab saved at this path.
+    Instance(String),
+    /// The child of the same name in the prefab its parent comes from. Children sharing a
+    /// name are told apart by a rank, `wheel#1` is the second one named `wheel`.
+    Part(String)
+}
+
+/// The name an entity spawned from a prefab is found by among its parent's children, kept
+/// when the entity is renamed.
+struct PrefabPart {
+    name: String
+}
+
+/// The registered components of an entity spawned from a prefab as they were spawned, with
+/// those of its parts by name.
+#[derive(Debug, PartialEq)]
+pub(crate) struct PrefabSnapshot {
+    components: BTreeMap<&'static str, Box<RawValue>>,
+    parts: HashMap<String, PrefabSnapshot>
+}
+
+#[derive(Serialize, Deserialize)]
+struct SceneFile {
+    version: u32,
+    entities: Vec<SavedEntity>
+}
+
+// `None` when the entity doesn't have the component
+type SaveFn = fn(&Scene, Entity, &AssetServer) -> Option<Result<Box<RawValue>, String>>;
+type LoadFn = fn(&mut Scene, Entity, &RawValue, &AssetServer) -> Result<(), String>;
+type Migration = Box<dyn Fn(&mut Vec<SavedEntity>)>;
+
+struct ComponentFormat {
+    save: SaveFn,
+    load: LoadFn
+}
+
+fn save_component<T: SceneComponent>(scene: &Scene, entity: Entity, assets: &AssetServer) -> Option<Result<Box<RawValue>, String>> {
+    let data = scene.get_component::<T>(entity)?.save(assets);
+    Some(to_raw(&data))
+}
+
+fn load_component<T: SceneComponent>(scene: &mut Scene, entity: Entity, raw: &RawValue, assets: &AssetServer) -> Result<(), String> {
+    let data = raw.into_rust::<T::Data>().map_err(|err| err.to_string())?;
+    scene.add_component(entity, T::load(data, assets));
+    Ok(())
+}
+
+/// The fields of a struct written in RON, `None` for any other value.
+fn struct_fields(raw: &RawValue) -> Option<BTreeMap<String, Box<RawValue>>> {
+    struct Fields(BTreeMap<String, Box<RawValue>>);
+
+    impl<'de> Deserialize<'de> for Fields {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Fields, D::Error> {
+            struct FieldsVisitor;
+
+            impl<'de> Visitor<'de> for FieldsVisitor {
+                type Value = Fields;
+
+                fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str("a struct")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Fields, A::Error> {
+                    let mut fields = BTreeMap::new();
+                    while let Some((name, value)) = map.next_entry::<String, Box<RawValue>>()? {
+                        fields.insert(name, value.trim_boxed());
+                    }
+                    Ok(Fields(fields))
+                }
+            }
+
+            deserializer.deserialize_any(FieldsVisitor)
+        }
+    }
+
+    // enum variants with fields read as structs too, losing the variant
+    if !raw.get_ron().trim_start().starts_with('(') {
+        return None;
+    }
+    raw.into_rust::<Fields>().ok().map(|fields| fields.0)
+}
+
+fn from_fields(fields: &BTreeMap<String, Box<RawValue>>) -> Result<Box<RawValue>, String> {
+    let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+    RawValue::from_boxed_ron(format!("({})", fields.join(", ")).into_boxed_str()).map_err(|err| err.to_string())
+}
+
+/// What changed from `original` to `raw`, only the changed fields when both are structs.
+fn changed_fields(original: &RawValue, raw: &RawValue) -> Result<Option<Box<RawValue>>, String> {
+    if original.trim().get_ron() == raw.trim().get_ron() {
+        return Ok(None);
+    }
+    match (struct_fields(original), struct_fields(raw)) {
+        (Some(original), Some(fields)) => {
+            let changed: BTreeMap<String, Box<RawValue>> = fields.into_iter()
+                .filter(|(name, value)| original.get(name).is_none_or(|original| original.get_ron() != value.get_ron()))
+                .collect();
+            if changed.is_empty() {
+                Ok(None)
+            } else {
+                from_fields(&changed).map(Some)
+            }
+        }
+        _ => Ok(Some(raw.to_owned()))
+    }
+}
+
+/// `raw` written over `current`, field by field when both are structs.
+fn merge_fields(current: &RawValue, raw: &RawValue) -> Result<Box<RawValue>, String> {
+    match (struct_fields(current), struct_fields(raw)) {
+        (Some(mut fields), Some(changed)) => {
+            fields.extend(changed);
+            from_fields(&fields)
+        }
+        _ => Ok(raw.to_owned())
+    }
+}
+
+fn find_part(scene: &Scene, parent: Entity, name: &str) -> Option<Entity> {
+    scene.get_children(parent)
+        .iter()
+        .copied()
+        .find(|&child| scene.get_component::<PrefabPart>(child).is_some_and(|part| part.name == name))
+}
+
+/// Names a child spawned from a prefab after its tag, ranked after the siblings named the same.
+fn name_part(scene: &mut Scene, parent: Entity, entity: Entity) {
+    let tag = scene.get_component::<Tag>(entity).map_or("", |tag| tag.name.as_str());
+    let name = (0..)
+        .map(|rank| if rank == 0 { tag.to_string() } else { format!("{}#{}", tag, rank) })
+        .find(|name| find_part(scene, parent, name).is_none())
+        .unwrap();
+    scene.add_component(entity, PrefabPart { name });
+}
+
+/// Writes a value as a single line of RON, so every component is one line of the file and
+/// changes to it show up as one line in diffs.
+pub fn to_raw<T: Serialize>(value: &T) -> Result<Box<RawValue>, String> {
+    let config = PrettyConfig::new().compact_structs(true).compact_arrays(true).compact_maps(true);
+    let ron = ron::ser::to_string_pretty(value, config).map_err(|err| err.to_string())?;
+    RawValue::from_boxed_ron(ron.into_boxed_str()).map_err(|err| err.to_string())
+}
+
+/// Saves scenes to and loads them from RON files listing every entity with its parent and
+/// registered components. Components that aren't registered aren't saved, world transforms
+/// are recomputed after loading.
+///
+/// Prefabs are trees of entities saved in the same format and spawned with `instantiate`.
+/// Instances are saved as the fields changed since they were spawned from their prefab, so
+/// they pick up later changes made to the prefab, and can be saved in other prefabs. Removing an instance's
+/// `PrefabInstance` saves it in full from then on.
+pub struct SceneSerializer {
+    components: BTreeMap<&'static str, ComponentFormat>,
+    renames: HashMap<String, String>,
+    migrations: BTreeMap<u32, Migration>,
+    // read once, with the migrations applied
+    prefabs: RefCell<HashMap<String, Rc<Vec<SavedEntity>>>>
+}
+
+impl SceneSerializer {
+    /// A serializer knowing the engine's components.
+    pub fn new() -> SceneSerializer {
+        let mut serializer = SceneSerializer {
+            components: BTreeMap::new(),
+            renames: HashMap::new(),
+            migrations: BTreeMap::new(),
+            prefabs: RefCell::new(HashMap::new())
+        };
+        serializer.register::<Tag>();
+        serializer.register::<Transform>();
+        serializer.register::<SpriteRenderer>();
+        serializer.register::<Camera>();
+        serializer.register::<BoxCollider>();
+        serializer
+    }
+
+    pub fn register<T: SceneComponent>(&mut self) {
+        let format = ComponentFormat {
+            save: save_component::<T>,
+            load: load_component::<T>
+        };
+        assert!(self.components.insert(T::NAME, format).is_none(), "{} is registered twice", T::NAME);
+    }
+
+    /// Loads components saved under `old_name` as the component now called `new_name`.
+    pub fn rename_component(&mut self, old_name: &str, new_name: &str) {
+        self.renames.insert(old_name.to_string(), new_name.to_string());
+    }
+
+    /// Upgrades files saved with `version` to the next version. Migrations run in order
+    /// before the renames and before any component is read.
+    pub fn add_migration<F: Fn(&mut Vec<SavedEntity>) + 'static>(&mut self, version: u32, migration: F) {
+        self.migrations.insert(version, Box::new(migration));
+    }
+
+    pub fn save(&self, scene: &Scene, assets: &AssetServer) -> Result<String, SceneError> {
+        let mut entities = vec![];
+        for root in scene.get_root_entities() {
+            self.save_entity(scene, root, None, None, &mut entities, assets)?;
+        }
+        self.write(entities)
+    }
+
+    pub fn load(&self, source: &str, assets: &AssetServer) -> Result<Scene, SceneError> {
+        let entities = self.read(source)?;
+        let mut scene = Scene::new();
+        self.spawn(&mut scene, &entities, assets, &mut vec![])?;
+        scene.update_world_transforms();
+        Ok(scene)
+    }
+
+    /// Saves `root` and its descendants as a prefab. `root` can itself be an instance of
+    /// another prefab, to save a variant of it.
+    pub fn save_prefab(&self, scene: &Scene, root: Entity, assets: &AssetServer) -> Result<String, SceneError> {
+        let mut entities = vec![];
+        self.save_entity(scene, root, None, None, &mut entities, assets)?;
+        self.write(entities)
+    }
+
+    /// Spawns an instance of the prefab saved at `path` as a new root entity, returning it.
+    /// Prefabs are read once and reused for later instances, see `reload_prefabs`.
+    pub fn instantiate(&self, scene: &mut Scene, path: &str, assets: &AssetServer) -> Result<Entity, SceneError> {
+        self.spawn_prefab(scene, path, assets, &mut vec![])
+    }
+
+    /// Reads the prefabs again the next time they are instantiated, to pick up changes made
+    /// to their files.
+    pub fn reload_prefabs(&self) {
+        self.prefabs.borrow_mut().clear();
+    }
+
+    fn write(&self, entities: Vec<SavedEntity>) -> Result<String, SceneError> {
+        let file = SceneFile {
+            version: SCENE_VERSION,
+            entities
+        };
+        ron::ser::to_string_pretty(&file, PrettyConfig::default()).map_err(|err| SceneError::Format(err.to_string()))
+    }
+
+    fn read(&self, source: &str) -> Result<Vec<SavedEntity>, SceneError> {
+        let mut file: SceneFile = ron::from_str(source).map_err(|err| SceneError::Format(err.to_string()))?;
+        if file.version > SCENE_VERSION {
+            return Err(SceneError::UnsupportedVersion(file.version));
+        }
+        for (_, migration) in self.migrations.range(file.version..SCENE_VERSION) {
+            migration(&mut file.entities);
+        }
+        Ok(file.entities)
+    }
+
+    /// Writes `entity` and its descendants, parents first. `original` is the entity as it was
+    /// spawned from its prefab, only what differs from it is written.
+    fn save_entity(&self, scene: &Scene, entity: Entity, parent: Option<u32>, original: Option<&PrefabSnapshot>, entities: &mut Vec<SavedEntity>, assets: &AssetServer) -> Result<(), SceneError> {
+        let (prefab, original) = match (original, scene.get_component::<PrefabInstance>(entity)) {
+            (Some(original), _) => {
+                let name = scene.get_component::<PrefabPart>(entity).map(|part| part.name.clone()).unwrap_or_default();
+                (Some(PrefabSource::Part(name)), Some(original))
+            }
+            (None, Some(instance)) => (Some(PrefabSource::Instance(instance.get_path().to_string())), Some(instance.get_original())),
+            (None, None) => (None, None)
+        };
+
+        let mut components = BTreeMap::new();
+        for (&name, format) in self.components.iter() {
+            let error = |err| SceneError::Component(name.to_string(), err);
+            let raw = match (format.save)(scene, entity, assets) {
+                Some(raw) => raw.map_err(error)?,
+                None => continue
+            };
+            let changed = match original.and_then(|original| original.components.get(name)) {
+                Some(original) => changed_fields(original, &raw).map_err(error)?,
+                None => Some(raw)
+            };
+            if let Some(changed) = changed {
+                components.insert(name.to_string(), changed);
+            }
+        }
+
+        let id = entities.len() as u32;
+        entities.push(SavedEntity {
+            id,
+            parent,
+            prefab,
+            components
+        });
+        for &child in scene.get_children(entity) {
+            // children added to an instance aren't in the prefab and are saved in full
+            let original_child = original.zip(scene.get_component::<PrefabPart>(child)).and_then(|(original, part)| original.parts.get(&part.name));
+            self.save_entity(scene, child, Some(id), original_child, entities, assets)?;
+        }
+
+        // unchanged parts without added children are found in the prefab
+        let saved = &entities[id as usize];
+        if matches!(saved.prefab, Some(PrefabSource::Part(_))) && saved.components.is_empty() && entities.len() == id as usize + 1 {
+            entities.pop();
+        }
+        Ok(())
+    }
+
+    /// Creates the saved entities in the scene, returning the roots. Nothing is left in the
+    /// scene when it fails.
+    fn spawn(&self, scene: &mut Scene, entities: &[SavedEntity], assets: &AssetServer, instancing: &mut Vec<String>) -> Result<Vec<Entity>, SceneError> {
+        let mut roots = vec![];
+        let result = self.spawn_entities(scene, entities, assets, instancing, &mut roots);
+        if result.is_err() {
+            for &root in roots.iter() {
+                scene.destroy_entity(root);
+            }
+        }
+        result.map(|()| roots)
+    }
+
+    fn spawn_entities(&self, scene: &mut Scene, entities: &[SavedEntity], assets: &AssetServer, instancing: &mut Vec<String>, roots: &mut Vec<Entity>) -> Result<(), SceneError> {
+        let mut ids = HashMap::new();
+        for saved in entities.iter() {
+            if ids.contains_key(&saved.id) {
+                return Err(SceneError::Format(format!("entity {} is saved twice", saved.id)));
+            }
+            let parent = match saved.parent {
+                Some(parent_id) => Some(*ids.get(&parent_id).ok_or(SceneError::InvalidParent(parent_id))?),
+                None => None
+            };
+
+            let entity = match (&saved.prefab, parent) {
+                (None, _) => scene.create_empty_entity(),
+                (Some(PrefabSource::Instance(path)), _) => self.spawn_prefab(scene, path, assets, instancing)?,
+                (Some(PrefabSource::Part(name)), Some(parent)) => match find_part(scene, parent, name) {
+                    Some(part) => part,
+                    None => {
+                        // the prefab no longer has the part, what was added below it goes to
+                        // its parent
+                        ids.insert(saved.id, parent);
+                        continue;
+                    }
+                },
+                (Some(PrefabSource::Part(name)), None) => return Err(SceneError::Format(format!("part {} has no parent", name)))
+            };
+            ids.insert(saved.id, entity);
+            match parent {
+                Some(parent) if scene.get_parent(entity) != Some(parent) => scene.attach(entity, Some(parent)),
+                Some(_) => (),
+                None => roots.push(entity)
+            }
+
+            for (name, raw) in saved.components.iter() {
+                let name = self.renames.get(name).unwrap_or(name);
+                let format = self.components.get(name.as_str()).ok_or_else(|| SceneError::UnknownComponent(name.clone()))?;
+                let error = |err| SceneError::Component(name.clone(), err);
+                let raw = match (format.save)(scene, entity, assets) {
+                    // entities spawned from prefabs only have their changes saved
+                    Some(current) => merge_fields(&current.map_err(error)?, raw).map_err(error)?,
+                    None => raw.to_owned()
+                };
+                (format.load)(scene, entity, &raw, assets).map_err(error)?;
+            }
+
+            // children in prefabs are named for the instances to find them by, parts of
+            // nested prefabs already are
+            let is_part = matches!(saved.prefab, Some(PrefabSource::Part(_)));
+            if let (Some(parent), false) = (parent, instancing.is_empty() || is_part) {
+                name_part(scene, parent, entity);
+            }
+        }
+        Ok(())
+    }
+
+    fn spawn_prefab(&self, scene: &mut Scene, path: &str, assets: &AssetServer, instancing: &mut Vec<String>) -> Result<Entity, SceneError> {
+        if instancing.iter().any(|other| other == path) {
+            return Err(SceneError::RecursivePrefab(path.to_string()));
+        }
+        let prefab = self.get_prefab(path, assets)?;
+        instancing.push(path.to_string());
+        let roots = self.spawn(scene, &prefab, assets, instancing);
+        instancing.pop();
+
+        let root = roots?[0];
+        let original = self.snapshot(scene, root, assets).inspect_err(|_| scene.destroy_entity(root))?;
+        scene.add_component(root, PrefabInstance::new(path, original));
+        Ok(root)
+    }
+
+    fn snapshot(&self, scene: &Scene, entity: Entity, assets: &AssetServer) -> Result<PrefabSnapshot, SceneError> {
+        let mut components = BTreeMap::new();
+        for (&name, format) in self.components.iter() {
+            if let Some(raw) = (format.save)(scene, entity, assets) {
+                components.insert(name, raw.map_err(|err| SceneError::Component(name.to_string(), err))?);
+            }
+        }
+        let mut parts = HashMap::new();
+        for &child in scene.get_children(entity) {
+            if let Some(part) = scene.get_component::<PrefabPart>(child) {
+                parts.insert(part.name.clone(), self.snapshot(scene, child, assets)?);
+            }
+        }
+        Ok(PrefabSnapshot { components, parts })
+    }
+
+    fn get_prefab(&self, path: &str, assets: &AssetServer) -> Result<Rc<Vec<SavedEntity>>, SceneError> {
+        if let Some(prefab) = self.prefabs.borrow().get(path) {
+            return Ok(prefab.clone());
+        }
+        let source = assets.get_vfs().read_to_string(path).map_err(|err| SceneError::Io(path.to_string(), err))?;
+        let entities = self.read(&source)?;
+        let roots = entities.iter().filter(|saved| saved.parent.is_none()).count();
+        if roots != 1 {
+            return Err(SceneError::Format(format!("prefab {} has {} roots instead of one", path, roots)));
+        }
+
+        let prefab = Rc::new(entities);
+        self.prefabs.borrow_mut().insert(path.to_string(), prefab.clone());
+        Ok(prefab)
+    }
+
+    /// Saves through the asset server's vfs, to the first directory mounted at the path's
+    /// mount point.
+    pub fn save_file(&self, scene: &Scene, path: &str, assets: &AssetServer) -> Result<(), SceneError> {
+        let source = self.save(scene, assets)?;
+        assets.get_vfs().write(path, source.as_bytes()).map_err(|err| SceneError::Io(path.to_string(), err))
+    }
+
+    pub fn load_file(&self, path: &str, assets: &AssetServer) -> Result<Scene, SceneError> {
+        let source = assets.get_vfs().read_to_string(path).map_err(|err| SceneError::Io(path.to_string(), err))?;
+        self.load(&source, assets)
+    }
+
+    /// Saves a prefab like `save_file`, instances spawned from then on use the new version.
+    pub fn save_prefab_file(&self, scene: &Scene, root: Entity, path: &str, assets: &AssetServer) -> Result<(), SceneError> {
+        let source = self.save_prefab(scene, root, assets)?;
+        assets.get_vfs().write(path, source.as_bytes()).map_err(|err| SceneError::Io(path.to_string(), err))?;
+        self.prefabs.borrow_mut().remove(path);
+        Ok(())
+    }
+}
+
+impl Default for SceneSerializer {
+    fn default() -> Self {
+        SceneSerializer::new()
+    }
+}
+
+impl SceneComponent for Tag {
+    const NAME: &'static str = "Tag";
+    type Data = Tag;
+
+    fn save(&self, _: &AssetServer) -> Tag {
+        self.clone()
+    }
+
+    fn load(data: Tag, _: &AssetServer) -> Tag {
+        data
+    }
+}
+
+impl SceneComponent for Transform {
+    const NAME: &'static str = "Transform";
+    type Data = Transform;
+
+    fn save(&self, _: &AssetServer) -> Transform {
+        *self
+    }
+
+    fn load(data: Transform, _: &AssetServer) -> Transform {
+        data
+    }
+}
+
+impl SceneComponent for Camera {
+    const NAME: &'static str = "Camera";
+    type Data = Camera;
+
+    fn save(&self, _: &AssetServer) -> Camera {
+        *self
+    }
+
+    fn load(data: Camera, _: &AssetServer) -> Camera {
+        data
+    }
+}
+
+impl SceneComponent for BoxCollider {
+    const NAME: &'static str = "BoxCollider";
+    type Data = BoxCollider;
+
+    fn save(&self, _: &AssetServer) -> BoxCollider {
+        *self
+    }
+
+    fn load(data: BoxCollider, _: &AssetServer) -> BoxCollider {
+        data
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SpriteRendererData {
+    pub color: Vec4,
+    pub texture: Option<String>,
+    pub sort_key: SortKey
+}
+
+impl SceneComponent for SpriteRenderer {
+    const NAME: &'static str = "SpriteRenderer";
+    type Data = SpriteRendererData;
+
+    fn save(&self, assets: &AssetServer) -> SpriteRendererData {
+        SpriteRendererData {
+            color: self.color,
+            texture: self.texture.as_ref().and_then(|texture| assets.get_texture(texture)).map(|texture| texture.get_path().clone()),
+            sort_key: self.sort_key
+        }
+    }
+
+    fn load(data: SpriteRendererData, assets: &AssetServer) -> SpriteRenderer {
+        SpriteRenderer {
+            color: data.color,
+            texture: data.texture.map(|path| assets.load_texture(&path, SamplerDesc::default())),
+            sort_key: data.sort_key
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::asset::AssetServer;
+    use crate::glm;
+    use crate::renderer::sorting::SortKey;
+    use crate::scene::Scene;
+    use crate::scene::components::{Camera, PrefabInstance, SpriteRenderer, Tag, Transform};
+    use crate::scene::serialize::{to_raw, SceneError, SceneSerializer};
+    use crate::vfs::MountSource;
+
+    // the directory is removed at the end of the test
+    fn prefab_assets(name: &str) -> (AssetServer, PathBuf) {
+        let assets = AssetServer::new();
+        let root = std::env::temp_dir().join(format!("adze_{}_{}", name, std::process::id()));
+        assets.mount("assets", MountSource::Directory(root.clone()));
+        (assets, root)
+    }
+
+    #[test]
+    fn saving_a_loaded_scene_gives_the_same_file() {
+        let assets = AssetServer::new();
+        let mut scene = Scene::new();
+        let camera = scene.create_entity("camera");
+        scene.add_component(camera, Camera::orthographic(4.0));
+        let ship = scene.create_entity("ship");
+        scene.add_component(ship, Transform::new(glm::vec3(0.1, -2.0, 0.5)).with_rotation(glm::vec3(0.0, 0.0, 0.3)));
+        let texture = assets.load_texture("textures/ship.png", Default::default());
+        scene.add_component(ship, SpriteRenderer::new(glm::vec4(1.0, 0.5, 0.25, 1.0)).with_texture(texture).with_sort_key(SortKey::new(2, 1)));
+        let turret = scene.create_entity("turret");
+        scene.set_parent(turret, Some(ship));
+
+        let serializer = SceneSerializer::new();
+        let saved = serializer.save(&scene, &assets).unwrap();
+        let loaded = serializer.load(&saved, &assets).unwrap();
+        assert_eq!(serializer.save(&loaded, &assets).unwrap(), saved);
+
+        let ship = loaded.find_entity("ship").unwrap();
+        let turret = loaded.find_entity("turret").unwrap();
+        assert_eq!(loaded.get_children(ship), &[turret]);
+        let sprite = loaded.get_component::<SpriteRenderer>(ship).unwrap();
+        assert_eq!(assets.get_texture(sprite.texture.as_ref().unwrap()).unwrap().get_path(), "textures/ship.png");
+        assert_eq!(loaded.get_component::<Camera>(loaded.find_entity("camera").unwrap()), Some(&Camera::orthographic(4.0)));
+    }
+
+    #[test]
+    fn old_files_are_migrated_and_renamed_components_found() {
+        let old = r#"(
+            version: 0,
+            entities: [
+                (id: 0, parent: None, components: {"Name": (name: "player")}),
+            ],
+        )"#;
+
+        let assets = AssetServer::new();
+        let mut serializer = SceneSerializer::new();
+        assert!(matches!(serializer.load(old, &assets), Err(SceneError::UnknownComponent(name)) if name == "Name"));
+
+        serializer.rename_component("Name", "Tag");
+        // version 0 had no transforms
+        serializer.add_migration(0, |entities| {
+            for entity in entities.iter_mut() {
+                entity.components.insert("Transform".to_string(), to_raw(&Transform::default()).unwrap());
+            }
+        });
+        let scene = serializer.load(old, &assets).unwrap();
+        let player = scene.find_entity("player").unwrap();
+        assert_eq!(scene.get_component::<Tag>(player), Some(&Tag::new("player")));
+        assert!(scene.has_component::<Transform>(player));
+
+        let newer = old.replace("version: 0", "version: 99");
+        assert!(matches!(serializer.load(&newer, &assets), Err(SceneError::UnsupportedVersion(99))));
+    }
+
+    #[test]
+    fn instances_keep_their_changes_when_the_prefab_changes() {
+        let (assets, root) = prefab_assets("instances");
+        let serializer = SceneSerializer::new();
+        let mut prefab = Scene::new();
+        let ship = prefab.create_entity("ship");
+        prefab.add_component(ship, SpriteRenderer::new(glm::vec4(1.0, 1.0, 1.0, 1.0)));
+        let turret = prefab.create_entity("turret");
+        prefab.add_component(turret, SpriteRenderer::new(glm::vec4(0.5, 0.5, 0.5, 1.0)));
+        prefab.set_parent(turret, Some(ship));
+        serializer.save_prefab_file(&prefab, ship, "prefabs/ship.ron", &assets).unwrap();
+
+        let mut scene = Scene::new();
+        serializer.instantiate(&mut scene, "prefabs/ship.ron", &assets).unwrap();
+        let changed = serializer.instantiate(&mut scene, "prefabs/ship.ron", &assets).unwrap();
+        assert_eq!(scene.get_component::<PrefabInstance>(changed).unwrap().get_path(), "prefabs/ship.ron");
+        scene.get_component_mut::<Transform>(changed).unwrap().translation.x = 5.0;
+        let changed_turret = scene.get_children(changed)[0];
+        scene.get_component_mut::<Tag>(changed_turret).unwrap().name = "big turret".to_string();
+        scene.get_component_mut::<SpriteRenderer>(changed_turret).unwrap().color = glm::vec4(1.0, 0.0, 0.0, 1.0);
+        let flag = scene.create_entity("flag");
+        scene.set_parent(flag, Some(changed_turret));
+        let saved = serializer.save(&scene, &assets).unwrap();
+        // only the changes are saved
+        assert!(!saved.contains("[0.5, 0.5, 0.5, 1.0]") && saved.contains("(translation: [5.0, 0.0, 0.0])"));
+
+        prefab.get_component_mut::<Transform>(ship).unwrap().scale = glm::vec3(2.0, 2.0, 1.0);
+        prefab.get_component_mut::<SpriteRenderer>(turret).unwrap().sort_key = SortKey::new(1, 0);
+        let shield = prefab.create_entity("shield");
+        prefab.set_parent(shield, Some(ship));
+        serializer.save_prefab_file(&prefab, ship, "prefabs/ship.ron", &assets).unwrap();
+
+        let loaded = serializer.load(&saved, &assets).unwrap();
+        let roots = loaded.get_root_entities();
+        assert_eq!(roots.len(), 2);
+        for &root in roots.iter() {
+            assert_eq!(loaded.get_component::<Transform>(root).unwrap().scale, glm::vec3(2.0, 2.0, 1.0));
+            assert_eq!(loaded.get_children(root).len(), 2);
+        }
+        assert_eq!(loaded.get_component::<Transform>(roots[1]).unwrap().translation, glm::vec3(5.0, 0.0, 0.0));
+        let big_turret = loaded.find_entity("big turret").unwrap();
+        let sprite = loaded.get_component::<SpriteRenderer>(big_turret).unwrap();
+        assert_eq!((sprite.color, sprite.sort_key), (glm::vec4(1.0, 0.0, 0.0, 1.0), SortKey::new(1, 0)));
+        assert_eq!(loaded.get_children(big_turret), &[loaded.find_entity("flag").unwrap()]);
+        assert_eq!(loaded.get_component::<Tag>(loaded.get_children(roots[0])[0]), Some(&Tag::new("turret")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn instances_saved_after_the_prefab_changes_only_keep_their_own_changes() {
+        let (assets, root) = prefab_assets("resaved");
+        let serializer = SceneSerializer::new();
+        let mut prefab = Scene::new();
+        let ship = prefab.create_entity("ship");
+        let turret = prefab.create_entity("turret");
+        prefab.set_parent(turret, Some(ship));
+        serializer.save_prefab_file(&prefab, ship, "prefabs/ship.ron", &assets).unwrap();
+
+        let mut scene = Scene::new();
+        let instance = serializer.instantiate(&mut scene, "prefabs/ship.ron", &assets).unwrap();
+        scene.get_component_mut::<Transform>(instance).unwrap().translation.x = 5.0;
+
+        prefab.get_component_mut::<Transform>(ship).unwrap().scale = glm::vec3(2.0, 2.0, 1.0);
+        prefab.get_component_mut::<Transform>(turret).unwrap().translation.y = 1.0;
+        serializer.save_prefab_file(&prefab, ship, "prefabs/ship.ron", &assets).unwrap();
+        // the instance still has the old scale and turret, they aren't changes of its own
+        let saved = serializer.save(&scene, &assets).unwrap();
+        assert!(!saved.contains("scale") && !saved.contains("turret"));
+
+        let loaded = serializer.load(&saved, &assets).unwrap();
+        let ship = loaded.find_entity("ship").unwrap();
+        let transform = loaded.get_component::<Transform>(ship).unwrap();
+        assert_eq!((transform.translation, transform.scale), (glm::vec3(5.0, 0.0, 0.0), glm::vec3(2.0, 2.0, 1.0)));
+        let turret = loaded.find_entity("turret").unwrap();
+        assert_eq!(loaded.get_component::<Transform>(turret).unwrap().translation.y, 1.0);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn prefabs_can_contain_instances_of_other_prefabs() {
+        let (assets, root) = prefab_assets("nested");
+        let serializer = SceneSerializer::new();
+        let mut prefab = Scene::new();
+        let wheel = prefab.create_entity("wheel");
+        serializer.save_prefab_file(&prefab, wheel, "prefabs/wheel.ron", &assets).unwrap();
+
+        let car = prefab.create_entity("car");
+        for x in [-1.0, 1.0] {
+            let wheel = serializer.instantiate(&mut prefab, "prefabs/wheel.ron", &assets).unwrap();
+            prefab.get_component_mut::<Transform>(wheel).unwrap().translation.x = x;
+            prefab.set_parent(wheel, Some(car));
+        }
+        serializer.save_prefab_file(&prefab, car, "prefabs/car.ron", &assets).unwrap();
+
+        let mut scene = Scene::new();
+        let car = serializer.instantiate(&mut scene, "prefabs/car.ron", &assets).unwrap();
+        let wheels = scene.get_children(car).to_vec();
+        assert_eq!(wheels.len(), 2);
+        assert_eq!(scene.get_component::<Transform>(wheels[1]).unwrap().translation.x, 1.0);
+        scene.get_component_mut::<Transform>(wheels[0]).unwrap().translation.y = -0.5;
+        let saved = serializer.save(&scene, &assets).unwrap();
+
+        prefab.get_component_mut::<Transform>(wheel).unwrap().scale = glm::vec3(0.5, 0.5, 1.0);
+        serializer.save_prefab_file(&prefab, wheel, "prefabs/wheel.ron", &assets).unwrap();
+        serializer.reload_prefabs();
+        let loaded = serializer.load(&saved, &assets).unwrap();
+        let car = loaded.find_entity("car").unwrap();
+        let wheels = loaded.get_children(car);
+        assert_eq!(loaded.get_component::<Transform>(wheels[0]).unwrap().translation, glm::vec3(-1.0, -0.5, 0.0));
+        for &wheel in wheels.iter() {
+            assert_eq!(loaded.get_component::<Transform>(wheel).unwrap().scale, glm::vec3(0.5, 0.5, 1.0));
+        }
+
+        // a wheel with a car in it would go on forever
+        let car = loaded.find_entity("car").unwrap();
+        let mut scene = loaded;
+        let wheel = scene.get_children(car)[0];
+        let inner_car = serializer.instantiate(&mut scene, "prefabs/car.ron", &assets).unwrap();
+        scene.set_parent(inner_car, Some(wheel));
+        serializer.save_prefab_file(&scene, wheel, "prefabs/wheel.ron", &assets).unwrap();
+        serializer.reload_prefabs();
+        let entity_count = scene.get_entity_count();
+        assert!(matches!(serializer.instantiate(&mut scene, "prefabs/car.ron", &assets), Err(SceneError::RecursivePrefab(_))));
+        assert_eq!(scene.get_entity_count(), entity_count);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use std::any::Any;
+
+use crate::scene::Entity;
+
+/// The components of one type, packed so queries walk them without gaps. `sparse` maps an
+/// entity's index to its slot in the packed arrays.
+pub struct ComponentStorage<T> {
+    sparse: Vec<Option<u32>>,
+    entities: Vec<Entity>,
+    components: Vec<T>
+}
+
+impl<T> ComponentStorage<T> {
+    pub(crate) fn new() -> ComponentStorage<T> {
+        ComponentStorage {
+            sparse: vec![],
+            entities: vec![],
+            components: vec![]
+        }
+    }
+
+    fn slot(&self, entity: Entity) -> Option<usize> {
+        let slot = (*self.sparse.get(entity.index as usize)?)? as usize;
+        // an older entity that had the same index doesn't own the component
+        if self.entities[slot] == entity { Some(slot) } else { None }
+    }
+
+    /// Adds the component, replacing and returning the one the entity already had.
+    pub(crate) fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(slot) = self.slot(entity) {
+            return Some(std::mem::replace(&mut self.components[slot], component));
+        }
+
+        let index = entity.index as usize;
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, None);
+        }
+        self.sparse[index] = Some(self.entities.len() as u32);
+        self.entities.push(entity);
+        self.components.push(component);
+        None
+    }
+
+    pub(crate) fn remove(&mut self, entity: Entity) -> Option<T> {
+        let slot = self.slot(entity)?;
+        self.sparse[entity.index as usize] = None;
+        self.entities.swap_remove(slot);
+        if let Some(moved) = self.entities.get(slot) {
+            self.sparse[moved.index as usize] = Some(slot as u32);
+        }
+        Some(self.components.swap_remove(slot))
+    }
+
+    pub(crate) fn get(&self, entity: Entity) -> Option<&T> {
+        self.slot(entity).map(|slot| &self.components[slot])
+    }
+
+    pub(crate) fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.slot(entity).map(move |slot| &mut self.components[slot])
+    }
+
+    pub(crate) fn get_entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Borrows only the component, leaving the entity list free to be read while a query
+    /// hands out the components.
+    ///
+    /// # Safety
+    /// `storage` must be valid and no other reference to this entity's component may be
+    /// alive for `'a`.
+    pub(crate) unsafe fn get_mut_raw<'a>(storage: *mut ComponentStorage<T>, entity: Entity) -> Option<&'a mut T> {
+        let slot = (*storage).slot(entity)?;
+        let components = &mut (*storage).components;
+        Some(&mut *components.as_mut_ptr().add(slot))
+    }
+}
+
+/// Lets the scene hold storages of every component type in one map.
+pub(crate) trait AnyStorage {
+    fn remove_entity(&mut self, entity: Entity);
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for ComponentStorage<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
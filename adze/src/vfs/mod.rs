@@ -0,0 +1,249 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::vfs::pack::{normalize_path, AssetPack};
+
+// kept in its own crate so tools writing packs don't need the renderer
+pub use adze_pack_format as pack;
+
+/// Mount point of paths without one.
+pub const DEFAULT_MOUNT: &str = "assets";
+
+/// Resources built into the engine, served under `engine://`.
+const ENGINE_FILES: &[(&str, &[u8])] = &[
+    ("shaders/sprite.glsl", include_bytes!("../../assets/shaders/sprite.glsl")),
+    ("shaders/mesh.glsl", include_bytes!("../../assets/shaders/mesh.glsl"))
+];
+
+/// Where the files under a mount point come from.
+#[derive(Clone)]
+pub enum MountSource {
+    Directory(PathBuf),
+    Pack(AssetPack),
+    Embedded(&'static [(&'static str, &'static [u8])])
+}
+
+impl MountSource {
+    fn contains(&self, path: &str) -> bool {
+        match self {
+            MountSource::Directory(dir) => dir.join(path).is_file(),
+            MountSource::Pack(pack) => pack.contains(path),
+            MountSource::Embedded(files) => files.iter().any(|(name, _)| *name == path)
+        }
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self {
+            MountSource::Directory(dir) => fs::read(dir.join(path)),
+            MountSource::Pack(pack) => pack.read(path),
+            MountSource::Embedded(files) => files.iter()
+                .find(|(name, _)| *name == path)
+                .map(|(_, data)| data.to_vec())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not built in", path)))
+        }
+    }
+}
+
+/// Splits `scheme://path` into the mount point and the normalized path under it. Paths
+/// without a scheme are under `assets://`. Paths with a `..` are rejected, they could reach
+/// files outside the directories mounted.
+pub fn split_path(path: &str) -> io::Result<(&str, String)> {
+    let (mount_point, relative) = match path.find("://") {
+        Some(index) => (&path[..index], &path[index + 3..]),
+        None => (DEFAULT_MOUNT, path)
+    };
+    let relative = normalize_path(relative).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} leaves its mount point", path)))?;
+    Ok((mount_point, relative))
+}
+
+/// The directory the running executable is in, shipped assets are looked up next to it.
+pub fn executable_dir() -> Option<PathBuf> {
+    env::current_exe().ok()?.parent().map(Path::to_path_buf)
+}
+
+/// The platform's directory for per user application data, like save games and settings.
+pub fn user_data_dir(app_name: &str) -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.map(|base| base.join(app_name))
+}
+
+/// Reads game files through mount points, so paths don't depend on the working directory.
+///
+/// A path like `assets://textures/Checkerboard.png` is looked up in every source mounted at
+/// `assets`, the most recently mounted first. Shipping builds mount a pack built with
+/// `adze-pack`, during development the asset folder is mounted over it so files can be edited
+/// and hot reloaded. `user://` is where save data is written and `engine://` holds the
+/// engine's built in resources.
+#[derive(Clone)]
+pub struct Vfs {
+    mounts: Vec<(String, MountSource)>
+}
+
+impl Vfs {
+    /// A filesystem with only `engine://` mounted.
+    pub fn new() -> Vfs {
+        let mut vfs = Vfs {
+            mounts: vec![]
+        };
+        vfs.mount("engine", MountSource::Embedded(ENGINE_FILES));
+        vfs
+    }
+
+    /// Mounts `assets://` to the `assets` folder and `assets.pack` next to the executable and
+    /// `user://` to the platform data directory for `app_name`.
+    pub fn with_defaults(app_name: &str) -> Vfs {
+        let mut vfs = Vfs::new();
+        if let Some(dir) = executable_dir() {
+            vfs.mount(DEFAULT_MOUNT, MountSource::Directory(dir.join("assets")));
+            if let Ok(pack) = AssetPack::open(dir.join("assets.pack")) {
+                vfs.mount(DEFAULT_MOUNT, MountSource::Pack(pack));
+            }
+        }
+        if let Some(dir) = user_data_dir(app_name) {
+            vfs.mount("user", MountSource::Directory(dir));
+        }
+        vfs
+    }
+
+    /// Adds a source to a mount point, it is searched before the ones mounted earlier.
+    pub fn mount(&mut self, mount_point: &str, source: MountSource) {
+        self.mounts.insert(0, (mount_point.to_string(), source));
+    }
+
+    pub fn unmount(&mut self, mount_point: &str) {
+        self.mounts.retain(|(point, _)| point != mount_point);
+    }
+
+    fn sources(&self, mount_point: &str) -> impl Iterator<Item = &MountSource> {
+        let mount_point = mount_point.to_string();
+        self.mounts.iter()
+            .filter(move |(point, _)| *point == mount_point)
+            .map(|(_, source)| source)
+    }
+
+    fn find(&self, path: &str) -> Option<(&MountSource, String)> {
+        let (mount_point, path) = split_path(path).ok()?;
+        self.sources(mount_point)
+            .find(|source| source.contains(&path))
+            .map(|source| (source, path))
+    }
+
+    /// The file on disk `path` is read from, `None` when it comes from a pack or is built in.
+    /// Files that don't exist yet resolve to the first directory of their mount point.
+    pub fn get_loose_path(&self, path: &str) -> Option<PathBuf> {
+        let (source, relative) = match self.find(path) {
+            Some(found) => found,
+            None => {
+                let (mount_point, relative) = split_path(path).ok()?;
+                (self.sources(mount_point).find(|source| matches!(source, MountSource::Directory(_)))?, relative)
+            }
+        };
+
+        match source {
+            MountSource::Directory(dir) => Some(dir.join(relative)),
+            _ => None
+        }
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self.find(path) {
+            Some((source, relative)) => source.read(&relative),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("{} was not found", path)))
+        }
+    }
+
+    pub fn read_to_string(&self, path: &str) -> io::Result<String> {
+        String::from_utf8(self.read(path)?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Writes a file to the first directory mounted at the path's mount point, creating the
+    /// directories leading to it.
+    pub fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        let (mount_point, relative) = split_path(path)?;
+        let dir = self.sources(mount_point)
+            .find_map(|source| match source {
+                MountSource::Directory(dir) => Some(dir),
+                _ => None
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, format!("{}:// has no directory to write to", mount_point)))?;
+
+        let file = dir.join(relative);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, data)
+    }
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Vfs::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+
+    use crate::vfs::{split_path, MountSource, Vfs};
+
+    #[test]
+    fn mounts_are_searched_newest_first() {
+        let root = std::env::temp_dir().join(format!("adze_vfs_{}", std::process::id()));
+        let mut vfs = Vfs::new();
+        vfs.mount("user", MountSource::Directory(root.join("user")));
+        vfs.mount("assets", MountSource::Directory(root.join("base")));
+        vfs.mount("assets", MountSource::Directory(root.join("patch")));
+
+        vfs.write("user://saves/one.txt", b"saved").unwrap();
+        assert_eq!(vfs.read_to_string("user://saves/one.txt").unwrap(), "saved");
+
+        fs::create_dir_all(root.join("base")).unwrap();
+        fs::write(root.join("base/level.txt"), "base").unwrap();
+        assert_eq!(vfs.read_to_string("level.txt").unwrap(), "base");
+        fs::create_dir_all(root.join("patch")).unwrap();
+        fs::write(root.join("patch/level.txt"), "patch").unwrap();
+        assert_eq!(vfs.read_to_string("assets://level.txt").unwrap(), "patch");
+
+        assert!(vfs.exists("engine://shaders/sprite.glsl"));
+        assert!(vfs.get_loose_path("engine://shaders/sprite.glsl").is_none());
+        assert!(!vfs.exists("missing://level.txt"));
+        assert_eq!(split_path("assets://./textures\\a.png").unwrap(), ("assets", "textures/a.png".to_string()));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn paths_cannot_leave_their_mount_point() {
+        let root = std::env::temp_dir().join(format!("adze_vfs_escape_{}", std::process::id()));
+        let mut vfs = Vfs::new();
+        vfs.mount("user", MountSource::Directory(root.join("user")));
+
+        let error = vfs.write("user://saves/../../escaped.txt", b"out").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(vfs.write("user://..\\escaped.txt", b"out").is_err());
+        assert!(!root.join("escaped.txt").exists());
+
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+        assert!(!vfs.exists("user://../secret.txt"));
+        assert!(vfs.read("user://../secret.txt").is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
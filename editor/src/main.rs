@@ -1,4 +1,5 @@
 use adze::app::App;
+use adze::asset::AssetServer;
 use adze::renderer::Renderer;
 use adze::app::event::EventListener;
 use adze::app::layer::Layer;
@@ -11,12 +12,14 @@ use adze::app::timer::Timer;
 use adze::egui_glow::EguiGlow;
 
 pub struct Editor {
+    asset_server: AssetServer
 }
 
 impl Editor {
-    pub fn new() -> Self {
+    pub fn new(asset_server: AssetServer) -> Self {
         let _timer = Timer::new("Editor::new");
         Editor {
+            asset_server
         }
     }
 }
@@ -29,6 +32,15 @@ impl EventListener for Editor {
             if ui.button("Quit").clicked() {
                 //quit = true;
             }
+
+            ui.separator();
+            ui.heading("Assets");
+            for info in self.asset_server.get_asset_infos() {
+                ui.label(format!("{:?} {} ({} handles, {:?})", info.kind, info.path, info.handle_count, info.state));
+            }
+            for path in self.asset_server.get_missing_paths() {
+                ui.colored_label(egui::Color32::RED, format!("Missing {}", path));
+            }
         });
     }
 }
@@ -45,7 +57,7 @@ impl Layer for Editor {
 
 fn main() {
     let mut app = App::new("sandbox");
-    let sandbox = Box::new(Editor::new());
+    let sandbox = Box::new(Editor::new(app.get_asset_server()));
     app.push_layer(sandbox);
     app.run();
 }